@@ -0,0 +1,322 @@
+//! A command-line client for a running fwm, through the Guile REPL socket
+//! it listens on.
+//!
+//! `fwmc navigate '(Planar . Left)'` calls `fwm-navigate` with the WM state
+//! and its arguments, quoted, and prints the result. `fwmc --eval EXPR`
+//! evaluates any expression, and `fwmc --wait-event` prints events from
+//! fwm's event socket as they happen.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(name = "fwmc")]
+struct Args {
+    /// The REPL socket, or with `--wait-event` the event socket. Defaults to
+    /// `$FWM_SOCKET` or `$FWM_EVENTS_SOCKET`, which fwm sets for the programs
+    /// it starts, or else the socket of the only fwm running.
+    #[clap(long)]
+    socket: Option<PathBuf>,
+    /// Print results as JSON. Events are always printed as JSON.
+    #[clap(long, conflicts_with = "wait-event")]
+    json: bool,
+    /// Evaluate a Scheme expression on the REPL's thread, instead of running a command.
+    #[clap(long, short, conflicts_with = "wait-event")]
+    eval: Option<String>,
+    /// Print events as they happen, one JSON object per line. The arguments
+    /// are the kinds to print, like `point-changed`, or all of them if there are none.
+    #[clap(long)]
+    wait_event: bool,
+    /// With `--wait-event`, exit after this many events.
+    #[clap(long, requires = "wait-event")]
+    count: Option<usize>,
+    /// A command, like `navigate` for `fwm-navigate`, and its arguments,
+    /// which are Scheme data.
+    args: Vec<String>,
+}
+
+/// Where a socket is, if it's not given: in the environment variable `var`,
+/// or else the only one named `$TMPDIR/<prefix><pid>` of an fwm that's running.
+fn find_socket(var: &str, prefix: &str) -> Result<PathBuf, String> {
+    match std::env::var_os(var) {
+        Some(socket) => Ok(socket.into()),
+        None => find_socket_in(&std::env::temp_dir(), var, prefix),
+    }
+}
+
+/// The only socket in `dir` named `<prefix><pid>` of a running process.
+fn find_socket_in(dir: &Path, var: &str, prefix: &str) -> Result<PathBuf, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut sockets = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let pid = match name.to_str().and_then(|name| name.strip_prefix(prefix)) {
+            Some(pid) if pid.parse::<u32>().is_ok() => pid.to_string(),
+            _ => continue,
+        };
+        // Sockets of fwms that exited are left behind.
+        if PathBuf::from("/proc").join(pid).exists() {
+            sockets.push(entry.path());
+        }
+    }
+    match sockets.len() {
+        0 => Err(format!("No running fwm found. Is ${} set?", var)),
+        1 => Ok(sockets.pop().unwrap()),
+        _ => Err("Several fwms are running; pick one with --socket".to_string()),
+    }
+}
+
+/// A connection to the REPL, which evaluates one expression at a time.
+struct Repl {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    /// Written around results, to find them among the REPL's prompts.
+    marker: String,
+}
+
+impl Repl {
+    fn connect(socket: &PathBuf) -> Result<Self, String> {
+        let writer =
+            UnixStream::connect(socket).map_err(|e| format!("{}: {}", socket.display(), e))?;
+        let reader = BufReader::new(writer.try_clone().map_err(|e| e.to_string())?);
+        Ok(Repl {
+            reader,
+            writer,
+            marker: format!("fwmc-{}", std::process::id()),
+        })
+    }
+
+    /// Evaluate `expr` and return its result, written as Scheme or JSON,
+    /// or a description of the exception it raised.
+    fn eval(&mut self, expr: &str, json: bool) -> Result<Result<String, String>, String> {
+        let marker = &self.marker;
+        let print = if json {
+            "(display (fwm-to-json value))"
+        } else {
+            "(write value)"
+        };
+        // `expr` is passed as a string and read by the REPL, so that nothing
+        // in it, like an unbalanced paren, can break out of this wrapper.
+        let expr = scheme_string(expr);
+        let wrapped = format!(
+            r#"(catch #t
+  (lambda ()
+    (let ((value (eval (call-with-input-string {expr} read) (current-module))))
+      (display "\n{marker}-ok\n")
+      (unless (unspecified? value) {print})
+      (display "\n{marker}-end\n")))
+  (lambda (key . args)
+    (display "\n{marker}-error\n")
+    (display key)
+    (display " ")
+    (write args)
+    (display "\n{marker}-end\n")))
+"#
+        );
+        self.writer
+            .write_all(wrapped.as_bytes())
+            .map_err(|e| e.to_string())?;
+        read_result(&mut self.reader, marker)
+    }
+}
+
+/// Read what `Repl::eval` wrote between its `marker`s, skipping the REPL's
+/// prompts and anything else before them.
+fn read_result(reader: &mut impl BufRead, marker: &str) -> Result<Result<String, String>, String> {
+    let mut ok = None;
+    let mut result = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("fwm closed the connection".to_string());
+        }
+        let trimmed = line.trim_end_matches('\n');
+        match ok {
+            None if trimmed == format!("{}-ok", marker) => ok = Some(true),
+            None if trimmed == format!("{}-error", marker) => ok = Some(false),
+            None => {}
+            Some(ok) if trimmed == format!("{}-end", marker) => {
+                // Drop the newline written before the end marker.
+                result.pop();
+                return Ok(if ok { Ok(result) } else { Err(result) });
+            }
+            Some(_) => result.push_str(&line),
+        }
+    }
+}
+
+/// `s` as a Scheme string literal.
+fn scheme_string(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            literal.push('\\');
+        }
+        literal.push(c);
+    }
+    literal.push('"');
+    literal
+}
+
+/// The expression for `fwmc COMMAND ARGS...`, which is run on the WM's thread.
+/// Each argument is read as one Scheme datum, by the REPL.
+fn command_expr(args: &[String]) -> Result<String, String> {
+    let (command, args) = args
+        .split_first()
+        .ok_or("Give a command, --eval or --wait-event")?;
+    if command.is_empty()
+        || !command
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-?!*<>=/+".contains(c))
+    {
+        return Err(format!("{:?} isn't the name of a command", command));
+    }
+    let args: String = args
+        .iter()
+        .map(|arg| format!(" (call-with-input-string {} read)", scheme_string(arg)))
+        .collect();
+    Ok(format!(
+        "(fwm-mt-sync (lambda (wm) (fwm-{} wm{})))",
+        command, args
+    ))
+}
+
+/// Print each event from the event socket whose kind is in `kinds`, or every
+/// event if `kinds` is empty, until `count` have been printed.
+fn wait_events(socket: &PathBuf, kinds: &[String], count: Option<usize>) -> Result<(), String> {
+    let stream = UnixStream::connect(socket).map_err(|e| format!("{}: {}", socket.display(), e))?;
+    let mut seen = 0;
+    if count == Some(0) {
+        return Ok(());
+    }
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if !kinds.is_empty() {
+            let event: serde_json::Value =
+                serde_json::from_str(&line).map_err(|e| e.to_string())?;
+            let kind = event["event"].as_str().unwrap_or_default();
+            if !kinds.iter().any(|k| k == kind) {
+                continue;
+            }
+        }
+        println!("{}", line);
+        seen += 1;
+        if count == Some(seen) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    if args.wait_event {
+        let socket = match args.socket {
+            Some(socket) => socket,
+            None => find_socket("FWM_EVENTS_SOCKET", "fwm-events.")?,
+        };
+        return wait_events(&socket, &args.args, args.count);
+    }
+    let socket = match args.socket {
+        Some(socket) => socket,
+        None => find_socket("FWM_SOCKET", "fwm.")?,
+    };
+    let mut repl = Repl::connect(&socket)?;
+    let expr = match args.eval {
+        Some(expr) => expr,
+        None => command_expr(&args.args)?,
+    };
+    let result = repl.eval(&expr, args.json)??;
+    if !result.is_empty() {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("fwmc: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn command_exprs() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            command_expr(&args(&["navigate", "(Planar . Left)"])).unwrap(),
+            r#"(fwm-mt-sync (lambda (wm) (fwm-navigate wm (call-with-input-string "(Planar . Left)" read))))"#
+        );
+        assert_eq!(
+            command_expr(&args(&["get-point"])).unwrap(),
+            "(fwm-mt-sync (lambda (wm) (fwm-get-point wm)))"
+        );
+        // Arguments can't close the expression early.
+        assert_eq!(
+            command_expr(&args(&["set-root-name", r#"("a\" b)") (system "x"#])).unwrap(),
+            r#"(fwm-mt-sync (lambda (wm) (fwm-set-root-name wm (call-with-input-string "(\"a\\\" b)\") (system \"x" read))))"#
+        );
+        assert!(command_expr(&[]).is_err());
+        assert!(command_expr(&args(&["get-point wm) (system \"x\""])).is_err());
+    }
+
+    #[test]
+    fn results_are_found_between_markers() {
+        let read = |output: &str| read_result(&mut Cursor::new(output), "fwmc-1");
+        assert_eq!(
+            read("scheme@(guile-user)> \nfwmc-1-ok\n(1 2)\nfwmc-1-end\n"),
+            Ok(Ok("(1 2)".to_string()))
+        );
+        assert_eq!(
+            read("\nfwmc-1-ok\nline one\nline two\nfwmc-1-end\n"),
+            Ok(Ok("line one\nline two".to_string()))
+        );
+        // Unspecified values print nothing.
+        assert_eq!(read("\nfwmc-1-ok\n\nfwmc-1-end\n"), Ok(Ok(String::new())));
+        assert_eq!(
+            read("\nfwmc-1-error\nmisc-error (#f \"oops\")\nfwmc-1-end\n"),
+            Ok(Err("misc-error (#f \"oops\")".to_string()))
+        );
+        // Another client's markers don't count.
+        assert_eq!(
+            read("fwmc-2-ok\nfwmc-1-ok\nmine\nfwmc-1-end\n"),
+            Ok(Ok("mine".to_string()))
+        );
+        assert!(read("fwmc-1-ok\ncut off").is_err());
+    }
+
+    #[test]
+    fn sockets_of_running_fwms_are_found() {
+        let dir = std::env::temp_dir().join(format!("fwmc-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let touch = |name: &str| std::fs::write(dir.join(name), "").unwrap();
+        // No process has a pid this large.
+        touch("fwm.4294967295");
+        touch("fwm.not-a-pid");
+        touch("other.1");
+        assert!(find_socket_in(&dir, "FWM_SOCKET", "fwm.").is_err());
+        let running = format!("fwm.{}", std::process::id());
+        touch(&running);
+        assert_eq!(
+            find_socket_in(&dir, "FWM_SOCKET", "fwm.").unwrap(),
+            dir.join(&running)
+        );
+        // The init process is always running.
+        touch("fwm.1");
+        assert!(find_socket_in(&dir, "FWM_SOCKET", "fwm.").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,56 +6,69 @@ use fwm::ChildLocation;
 
 use ::fwm::AreaSize;
 use ::fwm::Direction;
+use ::fwm::History;
 use ::fwm::ItemIdx;
 use ::fwm::Layout;
 use ::fwm::LayoutAction;
+use ::fwm::LayoutError;
 use ::fwm::MoveCursor;
 use ::fwm::WindowBounds;
 use fwm::Constructor;
+use fwm::ExportedRoot;
+use fwm::Gaps;
+use fwm::Imported;
 use fwm::ItemAndData;
-use fwm::LayoutDataMut;
+use fwm::LayoutDataRef;
 use fwm::LayoutStrategy;
+use fwm::Padding;
 use fwm::Position;
 use fwm::SlotInContainer;
 
+use btv_scheme::define_records;
+use btv_scheme::scheme_fn;
 use btv_scheme::Deserializer;
+use btv_scheme::EnumRepr;
+use btv_scheme::Foreign;
+use btv_scheme::ForeignObject;
+use btv_scheme::ForeignType;
+use btv_scheme::OptionRepr;
+use btv_scheme::Rooted;
+use btv_scheme::SchemeFnError;
 use btv_scheme::Serializer;
+use btv_scheme::SerializerConfig;
+use btv_scheme::StructRepr;
+use btv_scheme::SCM_UNDEFINED;
 use libc::umask;
 use log::error;
 use log::info;
+use log::warn;
 use mio::unix::SourceFd;
 use mio::Events;
 use mio::Interest;
 use mio::Poll;
 use mio::Token;
-use rust_guile::scm_apply_1;
-use rust_guile::scm_apply_2;
-use rust_guile::scm_assert_foreign_object_type;
+use rust_guile::scm_apply_0;
 use rust_guile::scm_assq_ref;
-use rust_guile::scm_c_define_gsubr;
+use rust_guile::scm_c_catch;
+use rust_guile::scm_c_lookup;
 use rust_guile::scm_car_unchecked;
 use rust_guile::scm_cdr_unchecked;
-use rust_guile::scm_foreign_object_ref;
-use rust_guile::scm_from_uint64;
-use rust_guile::scm_from_utf8_stringn;
+use rust_guile::scm_cons;
 use rust_guile::scm_from_utf8_symbol;
-use rust_guile::scm_gc_malloc_pointerless;
-use rust_guile::scm_gc_protect_object;
-use rust_guile::scm_gc_unprotect_object;
+use rust_guile::scm_is_exact_integer;
 use rust_guile::scm_is_pair;
 use rust_guile::scm_is_truthy;
-use rust_guile::scm_list_1;
-use rust_guile::scm_make_foreign_object_1;
-use rust_guile::scm_make_foreign_object_type;
+use rust_guile::scm_object_to_string;
 use rust_guile::scm_procedure_p;
 use rust_guile::scm_shell;
-use rust_guile::scm_to_uint64;
-use rust_guile::scm_to_utf8_stringn;
+use rust_guile::scm_variable_ref;
 use rust_guile::scm_with_guile;
-use rust_guile::size_t;
+use rust_guile::try_scm_to_string_or_sym;
 use rust_guile::SCM;
 use rust_guile::SCM_EOL;
 use rust_guile::SCM_UNSPECIFIED;
+use serde::de::DeserializeOwned;
+use serde::de::IgnoredAny;
 use serde::Deserialize;
 use serde::Serialize;
 use timely::progress::frontier::MutableAntichain;
@@ -104,6 +117,7 @@ use x11::xlib::XDestroyWindow;
 use x11::xlib::XDestroyWindowEvent;
 use x11::xlib::XErrorEvent;
 use x11::xlib::XEvent;
+use x11::xlib::XFetchName;
 use x11::xlib::XFree;
 use x11::xlib::XGetAtomName;
 use x11::xlib::XGetWMProtocols;
@@ -115,6 +129,7 @@ use x11::xlib::XKeyEvent;
 use x11::xlib::XKeycodeToKeysym;
 use x11::xlib::XKeysymToKeycode;
 use x11::xlib::XKeysymToString;
+use x11::xlib::XKillClient;
 use x11::xlib::XMapRequestEvent;
 use x11::xlib::XMapWindow;
 use x11::xlib::XMoveResizeWindow;
@@ -138,43 +153,35 @@ use x11::xlib::XWindowChanges;
 use x11::xlib::CWX;
 use x11::xlib::CWY;
 
-use std::cell::RefCell;
+use std::cell::Cell;
 use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::c_void;
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::ffi::OsString;
 use std::fmt::Debug;
-use std::marker::PhantomData;
+use std::io::Write;
 use std::mem::size_of;
 use std::mem::MaybeUninit;
 use std::os::raw::c_char;
 use std::os::raw::c_int;
 use std::os::raw::c_uchar;
 use std::os::raw::c_ulong;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
 use std::ptr::null;
 use std::ptr::null_mut;
 use std::rc::Rc;
-
-#[derive(Debug)]
-struct ProtectedScm(SCM);
-
-impl ProtectedScm {
-    pub unsafe fn new(x: SCM) -> Self {
-        scm_gc_protect_object(x);
-        Self(x)
-    }
-}
-
-impl Drop for ProtectedScm {
-    fn drop(&mut self) {
-        unsafe {
-            scm_gc_unprotect_object(self.0);
-        }
-    }
-}
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 struct X11ClientWindowData {
@@ -182,6 +189,35 @@ struct X11ClientWindowData {
     map_wanted: bool,
 }
 
+/// How long a client may ignore `WM_DELETE_WINDOW` (or fail to answer
+/// `_NET_WM_PING`) before we consider escalating to `XKillClient`.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The steps of the close protocol, passed to the `on-kill-step` hook
+/// so that Scheme code can confirm or cancel each one.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+enum KillStep {
+    /// Politely ask the client to close itself via `WM_DELETE_WINDOW`.
+    RequestDelete,
+    /// Forcibly disconnect the client with `XKillClient`.
+    ForceKill,
+}
+
+/// How many layout changes are remembered per root.
+const HISTORY_LIMIT: usize = 64;
+
+/// A client we have sent `WM_DELETE_WINDOW` to, but which hasn't gone away yet.
+#[derive(Debug, Copy, Clone)]
+struct PendingKill {
+    deadline: Instant,
+    /// True if we sent `_NET_WM_PING` and haven't heard back yet.
+    /// A client that answers the ping isn't hung, but may still be ignoring
+    /// the request (or asking the user whether to save), so this only
+    /// decides what's logged when the deadline passes; `on-kill-step`
+    /// decides whether to escalate.
+    awaiting_pong: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
 struct Rgb {
     r: u8,
@@ -226,45 +262,53 @@ impl WindowDecorationsTemplate {
         }
     }
 }
-const BASIC_DECO: WindowDecorationsTemplate =
-    WindowDecorationsTemplate::from_one(&WindowDecorationTemplate {
+/// The decorations of windows and containers, with and without the point.
+/// Set by the config's `theme`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+struct Theme {
+    window: WindowDecorationTemplate,
+    point_window: WindowDecorationTemplate,
+    container: WindowDecorationTemplate,
+    point_container: WindowDecorationTemplate,
+}
+
+const DEFAULT_THEME: Theme = Theme {
+    window: WindowDecorationTemplate {
         color: Rgb {
             r: 0,
             g: 0,
             b: 0xFF,
         },
         width: 3,
-    });
-const POINT_DECO: WindowDecorationsTemplate =
-    WindowDecorationsTemplate::from_one(&WindowDecorationTemplate {
+    },
+    point_window: WindowDecorationTemplate {
         color: Rgb {
             r: 0,
             g: 0xFF,
             b: 0,
         },
         width: 3,
-    });
-
-const BASIC_CTR_DECO: WindowDecorationsTemplate =
-    WindowDecorationsTemplate::from_one(&WindowDecorationTemplate {
+    },
+    container: WindowDecorationTemplate {
         color: Rgb {
             r: 0xCC,
             g: 0xCC,
             b: 0xCC,
         },
         width: 6,
-    });
-const POINT_CTR_DECO: WindowDecorationsTemplate =
-    WindowDecorationsTemplate::from_one(&WindowDecorationTemplate {
+    },
+    point_container: WindowDecorationTemplate {
         color: Rgb {
             r: 0xF4,
             g: 0x94,
             b: 0x01,
         },
         width: 6,
-    });
+    },
+};
 
 fn compute_deco(
+    theme: &Theme,
     item: ItemIdx,
     point: ItemIdx,
     cursor: Option<MoveCursor>,
@@ -299,12 +343,12 @@ fn compute_deco(
     });
 
     let is_at_point = item == point;
-    let mut deco = match (item, is_at_point) {
-        (ItemIdx::Window(_), true) => POINT_DECO,
-        (ItemIdx::Window(_), false) => BASIC_DECO,
-        (ItemIdx::Container(_), true) => POINT_CTR_DECO,
-        (ItemIdx::Container(_), false) => BASIC_CTR_DECO,
-    };
+    let mut deco = WindowDecorationsTemplate::from_one(match (item, is_at_point) {
+        (ItemIdx::Window(_), true) => &theme.point_window,
+        (ItemIdx::Window(_), false) => &theme.window,
+        (ItemIdx::Container(_), true) => &theme.point_container,
+        (ItemIdx::Container(_), false) => &theme.container,
+    });
 
     if let Some((move_direction, is_split)) = move_direction_and_split {
         let color = if is_split {
@@ -424,6 +468,7 @@ struct WindowData {
 struct ContainerDataConstructor {
     display: *mut Display,
     root: x11::xlib::Window,
+    template: WindowDecorationsTemplate,
 }
 
 impl Constructor for ContainerDataConstructor {
@@ -431,7 +476,7 @@ impl Constructor for ContainerDataConstructor {
 
     fn construct(&mut self) -> Self::Item {
         let decorations = unsafe { make_decorations(self.display, self.root) };
-        let template = BASIC_CTR_DECO;
+        let template = self.template;
         ContainerData {
             decorations,
             template,
@@ -445,10 +490,76 @@ struct ContainerData {
     template: WindowDecorationsTemplate,
 }
 
+/// The procedures from the config, each `#f` if it was left out.
+#[derive(Debug)]
+struct Hooks {
+    place_new_window: Rooted,
+    on_client_destroyed: Rooted,
+    on_point_changed: Rooted,
+    on_kill_step: Rooted,
+    on_client_orphaned: Rooted,
+    on_button1_pressed: Rooted,
+}
+
+/// A config alist, as taken by `fwm-run-wm` and `fwm-reconfigure`.
+struct Config {
+    bindings: Vec<(KeyCombo, Rooted)>,
+    hooks: Hooks,
+    theme: Theme,
+    padding: Padding,
+    gaps: Gaps,
+    smart_gaps: bool,
+}
+
+const DEFAULT_PADDING: Padding = Padding::uniform(6);
+
+impl Config {
+    /// Everything is checked here, so that a bad config can be rejected
+    /// before any of it is applied.
+    unsafe fn parse(config: SCM) -> Result<Self, BindingError> {
+        let get = |key: &CStr| scm_assq_ref(config, scm_from_utf8_symbol(key.as_ptr()));
+        let optional = |key: &CStr| Some(get(key)).filter(|&scm| scm_is_truthy(scm));
+        let hooks = Hooks {
+            place_new_window: Rooted::new(get(c"place-new-window")),
+            on_client_destroyed: Rooted::new(get(c"on-client-destroyed")),
+            on_point_changed: Rooted::new(get(c"on-point-changed")),
+            on_kill_step: Rooted::new(get(c"on-kill-step")),
+            on_client_orphaned: Rooted::new(get(c"on-client-orphaned")),
+            on_button1_pressed: Rooted::new(get(c"on-button1-pressed")),
+        };
+        let theme = match optional(c"theme") {
+            Some(theme) => Theme::deserialize(Deserializer::with_config(theme, SCHEME_CONFIG))?,
+            None => DEFAULT_THEME,
+        };
+        // The same on every side, or a record from `make-padding`.
+        let padding = match optional(c"padding") {
+            Some(padding) if scm_is_exact_integer(padding) != 0 => {
+                Padding::uniform(usize::deserialize(Deserializer::new(padding))?)
+            }
+            Some(padding) => {
+                Padding::deserialize(Deserializer::with_config(padding, SCHEME_CONFIG))?
+            }
+            None => DEFAULT_PADDING,
+        };
+        let gaps = match optional(c"gaps") {
+            Some(gaps) => Gaps::deserialize(Deserializer::with_config(gaps, SCHEME_CONFIG))?,
+            None => Gaps::default(),
+        };
+        Ok(Config {
+            bindings: parse_bindings(get(c"bindings"))?,
+            hooks,
+            theme,
+            padding,
+            gaps,
+            smart_gaps: bool::deserialize(Deserializer::new(get(c"smart-gaps")))?,
+        })
+    }
+}
+
 #[derive(Debug)]
 struct WmState {
     pub client_window_to_item_idx: HashMap<x11::xlib::Window, usize>,
-    pub bindings: HashMap<KeyCombo, ProtectedScm>,
+    pub bindings: HashMap<KeyCombo, Rooted>,
     pub layout: Layout<WindowData, ContainerData, ContainerDataConstructor>,
     pub point: ItemIdx,
     pub cursor: Option<MoveCursor>,
@@ -456,14 +567,36 @@ struct WmState {
     pub display: *mut x11::xlib::Display,
     pub root: x11::xlib::Window,
     pub focused: Option<usize>,
-    pub on_point_changed: ProtectedScm,
+    /// What `focused` was when focus was last set, to announce only changes.
+    announced_focus: Option<usize>,
+    pub hooks: Hooks,
+    pub theme: Theme,
     pub delete_window_atom: Atom,
     pub protocols_atom: Atom,
+    pub ping_atom: Atom,
+    pub pending_kills: HashMap<Window, PendingKill>,
     pub struts: HashMap<Window, StrutPartial>,
     pub struts_frontier: MutableAntichain<StrutPartial>,
     pub current_strut: StrutPartial,
     pub root_size: AreaSize,
     pub displayed_root: Option<usize>,
+    /// Hidden root backing the scratchpad; orphaned clients are also parked here by default.
+    pub scratch_root: Option<usize>,
+    /// Root holding windows summoned from the scratchpad as floating.
+    /// It is shown above the displayed root whenever it's non-empty.
+    pub float_root: Option<usize>,
+    /// Clients whose slots were destroyed or replaced, with those slots,
+    /// waiting to be re-homed at the end of the current `do_and_recompute`.
+    pub orphans: Vec<(X11ClientWindowData, ItemIdx)>,
+    /// The client each destroyed slot held, by slot, so that undoing the
+    /// destruction can take the client back from wherever it was re-homed.
+    pub orphaned_from: HashMap<usize, x11::xlib::Window>,
+    pub history: History,
+    /// Items changed by the actions of the outermost `do_and_recompute`, so far.
+    pub touched: Vec<ItemIdx>,
+    /// Nesting depth of `do_and_recompute`. History is only recorded at the outermost level,
+    /// so that e.g. re-homing orphans is undone along with whatever orphaned them.
+    pub recompute_depth: usize,
 }
 
 unsafe impl Send for WmState {}
@@ -476,39 +609,98 @@ fn outer_to_inner_size(outer: AreaSize, dt: &WindowDecorationsTemplate) -> AreaS
 }
 
 impl WmState {
+    /// The part of the screen not covered by struts, as bounds for `root_ctr`.
+    fn screen_bounds(&self, root_ctr: usize) -> WindowBounds {
+        let content_width = self
+            .root_size
+            .width
+            .saturating_sub((self.current_strut.left + self.current_strut.right) as usize);
+        let content_height = self
+            .root_size
+            .height
+            .saturating_sub((self.current_strut.top + self.current_strut.bottom) as usize);
+        WindowBounds {
+            position: Position {
+                x: self.current_strut.left as usize,
+                y: self.current_strut.top as usize,
+                root_ctr,
+            },
+            content: AreaSize {
+                height: content_height,
+                width: content_width,
+            },
+        }
+    }
     fn do_resize(&mut self) {
         if let Some(root_ctr) = self.displayed_root {
-            let content_width = self
-                .root_size
-                .width
-                .saturating_sub((self.current_strut.left + self.current_strut.right) as usize);
-            let content_height = self
-                .root_size
-                .height
-                .saturating_sub((self.current_strut.top + self.current_strut.bottom) as usize);
+            let new_bounds = self.screen_bounds(root_ctr);
+            if new_bounds != self.layout.bounds(ItemIdx::Container(root_ctr)) {
+                self.do_and_recompute(|wm| wm.layout.resize(new_bounds))
+            }
+        }
+        if let Some(float_root) = self.float_root {
+            // The floating layer covers the middle two thirds of the screen.
+            let WindowBounds {
+                content,
+                mut position,
+            } = self.screen_bounds(float_root);
+            let width = content.width * 2 / 3;
+            let height = content.height * 2 / 3;
+            position.x += (content.width - width) / 2;
+            position.y += (content.height - height) / 2;
             let new_bounds = WindowBounds {
-                position: Position {
-                    x: self.current_strut.left as usize,
-                    y: self.current_strut.top as usize,
-                    root_ctr,
-                },
-                content: AreaSize {
-                    height: content_height,
-                    width: content_width,
-                },
+                content: AreaSize { width, height },
+                position,
             };
-            if new_bounds != self.layout.bounds(ItemIdx::Container(root_ctr)) {
+            if new_bounds != self.layout.bounds(ItemIdx::Container(float_root)) {
                 self.do_and_recompute(|wm| wm.layout.resize(new_bounds))
             }
         }
     }
+    /// Whether items in `root_ctr` should currently be mapped.
+    fn is_root_visible(&self, root_ctr: usize) -> bool {
+        Some(root_ctr) == self.displayed_root
+            || (Some(root_ctr) == self.float_root
+                && self.layout.n_children(ItemIdx::Container(root_ctr)) > 0)
+    }
     unsafe fn call_on_point_changed(&mut self) {
-        let point = self.point.serialize(Serializer::default()).expect("XXX");
-        let on_point_changed = self.on_point_changed.0;
-        let scm = make_foreign_object_from_ref(self, WM_STATE_TYPE);
-        scm_apply_2(on_point_changed, scm.inner, point, SCM_EOL);
+        publish_event(Event::PointChanged { point: self.point });
+        let point = self.point;
+        self.call_hook::<IgnoredAny>(
+            "on-point-changed",
+            self.hooks.on_point_changed.get(),
+            || Ok(vec![point.serialize(serializer())?]),
+        );
+    }
+    /// Call `hook` with the WM state followed by `args`, and deserialize what it returns.
+    /// `None` if no hook was configured; also if it raised an exception or returned
+    /// something unexpected, which is logged, so that callers can fall back to a default.
+    unsafe fn call_hook<T: DeserializeOwned>(
+        &mut self,
+        name: &str,
+        hook: SCM,
+        args: impl FnOnce() -> Result<Vec<SCM>, btv_scheme::Error>,
+    ) -> Option<T> {
+        if !scm_is_truthy(hook) {
+            return None;
+        }
+        let result = args().map_err(|e| e.to_string()).and_then(|args| {
+            let result = WM_STATE.lend(self, |scm| {
+                apply_catching(hook, &[&[scm], args.as_slice()].concat())
+            })?;
+            T::deserialize(Deserializer::new(result)).map_err(|e| e.to_string())
+        });
+        result
+            .map_err(|e| error!("The {} hook failed: {}", name, e))
+            .ok()
     }
     unsafe fn ensure_focus(&mut self) {
+        if self.focused != self.announced_focus {
+            self.announced_focus = self.focused;
+            publish_event(Event::FocusChanged {
+                focused: self.focused.map(ItemIdx::Window),
+            });
+        }
         let mut did = false;
         if let Some(focused) = self.focused {
             if let WindowData {
@@ -540,14 +732,126 @@ impl WmState {
         }
     }
 
-    unsafe fn kill_window(&mut self, window: x11::xlib::Window) {
-        // TODO - gracefully kill the window - we will need to design a protocol
-        // to communicate with the layout about _attempting_ to kill windows.
-        // For now we just nuke it.
-
+    /// Destroy a window that we created ourselves (e.g., a decoration).
+    /// Client windows should go through `request_close_client` instead.
+    unsafe fn destroy_window(&mut self, window: x11::xlib::Window) {
         XDestroyWindow(self.display, window);
     }
 
+    /// Ask `on-kill-step` whether to go ahead with `step` for `window`.
+    /// If no hook was configured, or it failed, every step is allowed.
+    unsafe fn confirm_kill_step(&mut self, window: x11::xlib::Window, step: KillStep) -> bool {
+        let point = self
+            .client_window_to_item_idx
+            .get(&window)
+            .map(|&w_idx| ItemIdx::Window(w_idx));
+        self.call_hook("on-kill-step", self.hooks.on_kill_step.get(), || {
+            Ok(vec![
+                point.serialize(serializer())?,
+                step.serialize(serializer())?,
+            ])
+        })
+        .unwrap_or(true)
+    }
+
+    unsafe fn send_wm_protocol(&self, window: x11::xlib::Window, protocol: Atom) {
+        let mut cmd: ClientMessageData = Default::default();
+        cmd.set_long(0, protocol.try_into().unwrap());
+        cmd.set_long(1, CurrentTime.try_into().unwrap());
+        if protocol == self.ping_atom {
+            // The client echoes this back to the root window, so we can tell who answered.
+            cmd.set_long(2, window.try_into().unwrap());
+        }
+        let client_message = XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: 0,
+            display: self.display,
+            window,
+            message_type: self.protocols_atom,
+            format: 32,
+            data: cmd,
+        };
+        let mut ev = XEvent { client_message };
+        XSendEvent(self.display, window, 0, 0, &mut ev);
+    }
+
+    /// Close a client gracefully: send `WM_DELETE_WINDOW` if the client supports it
+    /// (escalating later if it doesn't comply), and otherwise kill it outright.
+    unsafe fn request_close_client(&mut self, window: x11::xlib::Window) {
+        if self.pending_kills.contains_key(&window) {
+            return;
+        }
+        if !self.supports_wm_protocol(window, self.delete_window_atom) {
+            self.force_kill_client(window);
+            return;
+        }
+        if !self.confirm_kill_step(window, KillStep::RequestDelete) {
+            return;
+        }
+        self.send_wm_protocol(window, self.delete_window_atom);
+        let awaiting_pong = self.supports_wm_protocol(window, self.ping_atom);
+        if awaiting_pong {
+            self.send_wm_protocol(window, self.ping_atom);
+        }
+        self.pending_kills.insert(
+            window,
+            PendingKill {
+                deadline: Instant::now() + KILL_TIMEOUT,
+                awaiting_pong,
+            },
+        );
+    }
+
+    unsafe fn force_kill_client(&mut self, window: x11::xlib::Window) {
+        self.pending_kills.remove(&window);
+        if self.confirm_kill_step(window, KillStep::ForceKill) {
+            info!("Force-killing client {window}");
+            XKillClient(self.display, window);
+        } else {
+            info!("on-kill-step declined to force-kill client {window}");
+        }
+    }
+
+    /// Called when a client answers `_NET_WM_PING`.
+    fn record_pong(&mut self, window: x11::xlib::Window) {
+        if let Some(pk) = self.pending_kills.get_mut(&window) {
+            pk.awaiting_pong = false;
+        }
+    }
+
+    /// How long the event loop may sleep before some pending kill times out.
+    fn next_kill_timeout(&self) -> Option<Duration> {
+        self.pending_kills
+            .values()
+            .map(|pk| pk.deadline.saturating_duration_since(Instant::now()))
+            .min()
+    }
+
+    /// Offer to escalate every pending kill whose deadline has passed.
+    /// Even a client that answered a ping is offered up, since being alive
+    /// doesn't mean it will ever honor the request; `on-kill-step` can
+    /// spare it.
+    unsafe fn escalate_expired_kills(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .pending_kills
+            .iter()
+            .filter(|(_, pk)| pk.deadline <= now)
+            .map(|(&window, pk)| (window, pk.awaiting_pong))
+            .collect();
+        for (window, awaiting_pong) in expired {
+            // If the client didn't support pings, we have no way of knowing whether it's hung.
+            let answered_ping = !awaiting_pong && self.supports_wm_protocol(window, self.ping_atom);
+            if answered_ping {
+                info!("Client {window} is alive but didn't close in time; escalating");
+            } else {
+                info!("Client {window} didn't close in time and may be hung; escalating");
+            }
+            self.force_kill_client(window);
+        }
+    }
+
     unsafe fn update_window_bounds(&mut self, window_idx: usize) {
         let WindowData {
             client, template, ..
@@ -597,6 +901,10 @@ impl WmState {
         let mut possibly_affected = vec![old_point, new_point];
         for cur in &[old_cursor, new_cursor] {
             if let Some(cur) = cur {
+                if !self.layout.is_cursor_valid(*cur) {
+                    // E.g., its container was destroyed by undo.
+                    continue;
+                }
                 match cur {
                     MoveCursor::Split { item, .. } => possibly_affected.push(*item),
                     MoveCursor::Into { container, index } => {
@@ -619,7 +927,7 @@ impl WmState {
         for item in possibly_affected {
             if self.layout.exists(item) {
                 let where_is_it = self.layout.slot_in_container(item);
-                let t = compute_deco(item, new_point, new_cursor, where_is_it);
+                let t = compute_deco(&self.theme, item, new_point, new_cursor, where_is_it);
                 let bounds = self.layout.bounds(item);
                 let mt = self.try_template_mut(item).unwrap();
                 if *mt != t {
@@ -630,7 +938,7 @@ impl WmState {
                         bounds,
                         self.try_decorations(item).unwrap(),
                         rt,
-                        Some(bounds.position.root_ctr) == self.displayed_root,
+                        self.is_root_visible(bounds.position.root_ctr),
                     );
                 }
             }
@@ -655,49 +963,66 @@ impl WmState {
         }
     }
 
-    unsafe fn unmap_all(&self) {
-        if let Some(displayed_root) = self.displayed_root {
-            for item in self
-                .layout
-                .iter_descendants(ItemIdx::Container(displayed_root))
-            {
-                if let Some(decos) = self.try_decorations(item) {
-                    for deco in [decos.up, decos.left, decos.down, decos.right] {
-                        self.request_unmap(deco);
-                    }
+    /// Map or unmap the decorations and clients of `item` and all its descendants.
+    unsafe fn set_subtree_mapped(&self, item: ItemIdx, mapped: bool) {
+        for item in self.layout.iter_descendants(item) {
+            let mut windows = vec![];
+            if let Some(decos) = self.try_decorations(item) {
+                windows.extend([decos.up, decos.left, decos.down, decos.right]);
+            }
+            if let ItemIdx::Window(w_idx) = item {
+                if let Some(client) = self
+                    .layout
+                    .try_window_data(w_idx)
+                    .and_then(|data| data.client.as_ref())
+                {
+                    windows.push(client.window);
                 }
-                if let ItemIdx::Window(w_idx) = item {
-                    if let Some(client) = self
-                        .layout
-                        .try_window_data(w_idx)
-                        .and_then(|data| data.client.as_ref())
-                    {
-                        self.request_unmap(client.window);
-                    }
+            }
+            for window in windows {
+                if mapped {
+                    self.request_map(window);
+                } else {
+                    self.request_unmap(window);
                 }
             }
         }
     }
 
+    unsafe fn unmap_all(&self) {
+        if let Some(displayed_root) = self.displayed_root {
+            self.set_subtree_mapped(ItemIdx::Container(displayed_root), false);
+        }
+    }
+
     unsafe fn map_all(&self) {
         if let Some(displayed_root) = self.displayed_root {
-            for item in self
-                .layout
-                .iter_descendants(ItemIdx::Container(displayed_root))
-            {
-                if let Some(decos) = self.try_decorations(item) {
-                    for deco in [decos.up, decos.left, decos.down, decos.right] {
-                        self.request_map(deco);
-                    }
+            self.set_subtree_mapped(ItemIdx::Container(displayed_root), true);
+        }
+        self.raise_float_layer();
+    }
+
+    /// Keep the floating layer stacked above everything else.
+    unsafe fn raise_float_layer(&self) {
+        let Some(float_root) = self.float_root else {
+            return;
+        };
+        if !self.is_root_visible(float_root) {
+            return;
+        }
+        for item in self.layout.iter_descendants(ItemIdx::Container(float_root)) {
+            if let Some(decos) = self.try_decorations(item) {
+                for deco in [decos.up, decos.left, decos.down, decos.right] {
+                    XRaiseWindow(self.display, deco);
                 }
-                if let ItemIdx::Window(w_idx) = item {
-                    if let Some(client) = self
-                        .layout
-                        .try_window_data(w_idx)
-                        .and_then(|data| data.client.as_ref())
-                    {
-                        self.request_map(client.window);
-                    }
+            }
+            if let ItemIdx::Window(w_idx) = item {
+                if let Some(client) = self
+                    .layout
+                    .try_window_data(w_idx)
+                    .and_then(|data| data.client.as_ref())
+                {
+                    XRaiseWindow(self.display, client.window);
                 }
             }
         }
@@ -705,27 +1030,43 @@ impl WmState {
 }
 
 impl WmState {
-    pub fn new<'a>(
+    pub unsafe fn new(
         display: *mut x11::xlib::Display,
         root: x11::xlib::Window,
         size: AreaSize,
-        on_point_changed: ProtectedScm,
+        config: Config,
     ) -> Self {
         let delete_window_atom =
             unsafe { XInternAtom(display, std::mem::transmute(b"WM_DELETE_WINDOW\0"), 0) };
         let protocols_atom =
             unsafe { XInternAtom(display, std::mem::transmute(b"WM_PROTOCOLS\0"), 0) };
-        let cctor = ContainerDataConstructor { display, root };
-        let layout = Layout::new(cctor, 6);
+        let ping_atom = unsafe { XInternAtom(display, c(b"_NET_WM_PING\0"), 0) };
+        let Config {
+            bindings,
+            hooks,
+            theme,
+            padding,
+            gaps,
+            smart_gaps,
+        } = config;
+        let cctor = ContainerDataConstructor {
+            display,
+            root,
+            template: WindowDecorationsTemplate::from_one(&theme.container),
+        };
+        let mut layout = Layout::new(cctor, padding, gaps);
+        layout.set_smart_gaps(smart_gaps);
 
-        Self {
+        let mut wm = Self {
             client_window_to_item_idx: Default::default(),
             bindings: Default::default(),
-            on_point_changed,
+            hooks,
+            theme,
             layout,
             point: ItemIdx::Container(0), // XXX invalid point. Need to rethink how points work. Optional?
             cursor: None,
             focused: None,
+            announced_focus: None,
             struts: Default::default(),
             struts_frontier: MutableAntichain::new(),
             current_strut: Default::default(),
@@ -735,7 +1076,93 @@ impl WmState {
             root,
             delete_window_atom,
             protocols_atom,
+            ping_atom,
+            pending_kills: Default::default(),
             displayed_root: None,
+            scratch_root: None,
+            float_root: None,
+            orphans: vec![],
+            orphaned_from: Default::default(),
+            history: History::new(HISTORY_LIMIT),
+            touched: vec![],
+            recompute_depth: 0,
+        };
+        wm.grab_bindings(bindings);
+        wm
+    }
+
+    /// Switch to `config`, leaving the layout tree alone: the hooks and
+    /// bindings are replaced, and every item is redrawn with the new theme,
+    /// padding and gaps.
+    unsafe fn reconfigure(&mut self, config: Config) {
+        let Config {
+            bindings,
+            hooks,
+            theme,
+            padding,
+            gaps,
+            smart_gaps,
+        } = config;
+        self.hooks = hooks;
+        self.ungrab_bindings();
+        self.grab_bindings(bindings);
+        self.announce_bindings();
+
+        self.theme = theme;
+        self.layout.constructor_mut().template =
+            WindowDecorationsTemplate::from_one(&theme.container);
+        let items: Vec<_> = self
+            .layout
+            .roots()
+            .flat_map(|root| self.layout.iter_descendants(ItemIdx::Container(root)))
+            .collect();
+        for &item in &items {
+            let where_is_it = self.layout.slot_in_container(item);
+            let t = compute_deco(&self.theme, item, self.point, self.cursor, where_is_it);
+            *self.try_template_mut(item).unwrap() = t;
+        }
+        self.do_and_recompute(|wm| {
+            let mut actions = wm.layout.set_default_padding(padding);
+            actions.extend(wm.layout.set_default_gaps(gaps));
+            actions.extend(wm.layout.set_smart_gaps(smart_gaps));
+            // Items that didn't move still need redrawing.
+            actions.extend(items.iter().map(|&idx| LayoutAction::NewBounds {
+                idx,
+                bounds: wm.layout.bounds(idx),
+            }));
+            actions
+        });
+    }
+
+    unsafe fn grab_bindings(&mut self, bindings: Vec<(KeyCombo, Rooted)>) {
+        for (kc, proc) in bindings {
+            XGrabKey(
+                self.display,
+                XKeysymToKeycode(self.display, kc.key_sym.into()).into(),
+                kc.x_modifiers(),
+                self.root,
+                0,
+                GrabModeAsync,
+                GrabModeAsync,
+            );
+            self.bindings.insert(kc, proc);
+        }
+    }
+
+    fn announce_bindings(&self) {
+        let mut bindings: Vec<_> = self.bindings.keys().map(KeyCombo::to_string).collect();
+        bindings.sort();
+        publish_event(Event::BindingsChanged { bindings });
+    }
+
+    unsafe fn ungrab_bindings(&mut self) {
+        for (kc, _) in self.bindings.drain() {
+            XUngrabKey(
+                self.display,
+                XKeysymToKeycode(self.display, kc.key_sym.into()).into(),
+                kc.x_modifiers(),
+                self.root,
+            );
         }
     }
 
@@ -743,6 +1170,308 @@ impl WmState {
         self.layout.alloc_root()
     }
 
+    pub fn scratch_root(&mut self) -> usize {
+        match self.scratch_root {
+            Some(root) => root,
+            None => {
+                let root = self.alloc_root();
+                self.layout.set_root_name(root, Some("scratch".to_owned()));
+                self.scratch_root = Some(root);
+                root
+            }
+        }
+    }
+
+    /// The end of the scratch root, where clients go when there's nowhere better.
+    fn scratch_placement(&mut self) -> MoveOrReplace {
+        let scratch_root = self.scratch_root();
+        let index = self.layout.n_children(ItemIdx::Container(scratch_root));
+        MoveOrReplace::Move(MoveCursor::Into {
+            container: scratch_root,
+            index,
+        })
+    }
+    /// Whether `placement`, which may have come from a hook, can be used by `manage_client`.
+    fn check_placement(&self, placement: &MoveOrReplace) -> Result<(), BindingError> {
+        match *placement {
+            MoveOrReplace::Move(cursor) => Ok(self.layout.check_cursor(cursor)?),
+            MoveOrReplace::Replace(ItemIdx::Window(w_idx)) => {
+                Ok(self.layout.check_item(ItemIdx::Window(w_idx))?)
+            }
+            MoveOrReplace::Replace(ItemIdx::Container(c_idx)) => Err(BindingError::Invalid(
+                format!("Container {} has no client to replace", c_idx),
+            )),
+        }
+    }
+    /// Start managing `window` in the slot described by `placement`,
+    /// or at the end of the scratch root if that slot isn't valid.
+    /// The client is mapped iff it landed on the displayed root.
+    unsafe fn manage_client(
+        &mut self,
+        window: Window,
+        mut placement: MoveOrReplace,
+        mut take_point: bool,
+    ) {
+        if let Err(e) = self.check_placement(&placement) {
+            error!(
+                "Can't place client {} as asked ({}); sending it to the scratch root",
+                window, e
+            );
+            placement = self.scratch_placement();
+            take_point = false;
+        }
+        let client = X11ClientWindowData {
+            window,
+            map_wanted: true,
+        };
+        self.do_and_recompute(|wm| match placement {
+            MoveOrReplace::Move(insert_cursor) => {
+                let root_ctr = wm.layout.bounds(insert_cursor.item()).position.root_ctr;
+                let decorations = make_decorations(wm.display, wm.root);
+                let w_idx = wm.layout.alloc_window(
+                    WindowData {
+                        client: Some(client),
+                        decorations,
+                        template: WindowDecorationsTemplate::from_one(&wm.theme.window),
+                    },
+                    root_ctr,
+                );
+                wm.client_window_to_item_idx.insert(window, w_idx);
+                let actions = wm.layout.r#move(ItemIdx::Window(w_idx), insert_cursor);
+                if take_point {
+                    wm.point = ItemIdx::Window(w_idx);
+                }
+                XRaiseWindow(wm.display, window);
+                actions
+            }
+            MoveOrReplace::Replace(ItemIdx::Window(w_idx)) => {
+                let old_bounds = wm.layout.bounds(ItemIdx::Window(w_idx));
+                let old_client = std::mem::replace(
+                    &mut wm.layout.try_window_data_mut(w_idx).unwrap().client,
+                    Some(client),
+                );
+                if let Some(old_client) = old_client {
+                    // Don't kill whatever was there before; just find it a new home.
+                    wm.orphan_client(old_client, ItemIdx::Window(w_idx));
+                }
+                wm.client_window_to_item_idx.insert(window, w_idx);
+                if take_point {
+                    wm.point = ItemIdx::Window(w_idx);
+                }
+                XRaiseWindow(wm.display, window);
+                vec![LayoutAction::NewBounds {
+                    idx: ItemIdx::Window(w_idx),
+                    bounds: old_bounds,
+                }]
+            }
+            MoveOrReplace::Replace(ItemIdx::Container(_)) => {
+                unreachable!("rejected by check_placement")
+            }
+        });
+        let w_idx = self.client_window_to_item_idx[&window];
+        publish_event(Event::ClientMapped {
+            point: ItemIdx::Window(w_idx),
+            window,
+        });
+        if self.is_root_visible(self.layout.bounds(ItemIdx::Window(w_idx)).position.root_ctr) {
+            self.request_map(window);
+            self.raise_float_layer();
+        } else {
+            self.request_unmap(window);
+        }
+    }
+
+    /// Detach `client` from `old_point`, the slot it was in. It will be
+    /// re-homed at the end of the current `do_and_recompute`.
+    fn orphan_client(&mut self, client: X11ClientWindowData, old_point: ItemIdx) {
+        self.client_window_to_item_idx.remove(&client.window);
+        self.orphans.push((client, old_point));
+    }
+
+    /// Find a new slot for a client whose slot, `old_point`, went away, as decided
+    /// by the `on-client-orphaned` hook (defaulting to the scratch root).
+    unsafe fn rehome_client(&mut self, client: X11ClientWindowData, old_point: ItemIdx) {
+        let hook = self.hooks.on_client_orphaned.get();
+        let action = self
+            .call_hook("on-client-orphaned", hook, || {
+                Ok(vec![
+                    client.window.serialize(serializer())?,
+                    old_point.serialize(serializer())?,
+                ])
+            })
+            .unwrap_or(OrphanAction::Scratch);
+        info!("Re-homing orphaned client {}", client.window);
+        match action {
+            OrphanAction::Close => self.request_close_client(client.window),
+            OrphanAction::Scratch => {
+                let placement = self.scratch_placement();
+                self.manage_client(client.window, placement, false);
+            }
+            OrphanAction::Place(placement) => self.manage_client(client.window, placement, true),
+        }
+    }
+
+    fn float_root(&mut self) -> usize {
+        match self.float_root {
+            Some(root) => root,
+            None => {
+                let root = self.alloc_root();
+                self.layout.set_root_name(root, Some("float".to_owned()));
+                self.float_root = Some(root);
+                self.do_resize();
+                root
+            }
+        }
+    }
+
+    /// Move `item` and its descendants with `mv`, keeping the point out of hidden roots,
+    /// and map or unmap the subtree according to whether its new root is visible.
+    /// Nothing changes if `mv` fails.
+    unsafe fn relocate(
+        &mut self,
+        item: ItemIdx,
+        mv: impl FnOnce(
+            &mut Layout<WindowData, ContainerData, ContainerDataConstructor>,
+        ) -> Result<Vec<LayoutAction<WindowData, ContainerData>>, LayoutError>,
+    ) -> Result<(), LayoutError> {
+        self.layout.check_non_root(item)?;
+        let old_root = self.layout.root_of(item);
+        self.try_do_and_recompute(|wm| {
+            let point_moved = wm.layout.is_ancestor(item, wm.point);
+            let next = wm.layout.topological_next(item);
+            let actions = mv(&mut wm.layout)?;
+            if point_moved {
+                wm.point = next
+                    .filter(|&next| wm.layout.exists(next))
+                    .or_else(|| {
+                        wm.displayed_root
+                            .map(|displayed_root| wm.layout.topological_last(displayed_root))
+                    })
+                    .unwrap_or(ItemIdx::Container(0));
+            }
+            Ok(actions)
+        })?;
+        let new_root = self.layout.root_of(item).unwrap();
+        self.set_subtree_mapped(item, self.is_root_visible(new_root));
+        if let Some(old_root) = old_root {
+            if !self.is_root_visible(old_root) {
+                // E.g., the floating layer just became empty and should disappear.
+                self.set_subtree_mapped(ItemIdx::Container(old_root), false);
+            }
+        }
+        self.raise_float_layer();
+        Ok(())
+    }
+
+    /// Destroy `root`, moving its children to `rehome` if given.
+    /// The point is moved to the displayed root if it was in `root`.
+    unsafe fn destroy_root(
+        &mut self,
+        root: usize,
+        rehome: Option<usize>,
+    ) -> Result<(), LayoutError> {
+        self.try_do_and_recompute(|wm| {
+            let point_destroyed = wm.layout.is_ancestor(ItemIdx::Container(root), wm.point);
+            let actions = wm.layout.checked_destroy_root(root, rehome)?;
+            if point_destroyed {
+                wm.point = wm
+                    .displayed_root
+                    .map(|displayed_root| wm.layout.topological_last(displayed_root))
+                    .unwrap_or(ItemIdx::Container(0));
+            }
+            Ok(actions)
+        })?;
+        if let Some(rehome) = rehome {
+            self.set_subtree_mapped(ItemIdx::Container(rehome), self.is_root_visible(rehome));
+            self.raise_float_layer();
+        }
+        Ok(())
+    }
+
+    /// Hide `item` in the scratchpad.
+    unsafe fn send_to_scratch(&mut self, item: ItemIdx) -> Result<(), LayoutError> {
+        let scratch_root = self.scratch_root();
+        self.send_to_root(item, scratch_root)
+    }
+
+    /// Build a new root from `exported`, moving the clients it names that are
+    /// still managed into their new slots. Their old slots are left empty.
+    unsafe fn import_layout(
+        &mut self,
+        exported: &ExportedRoot<ExportedWindow>,
+    ) -> Result<usize, LayoutError> {
+        let (display, root_window) = (self.display, self.root);
+        let template = WindowDecorationsTemplate::from_one(&self.theme.window);
+        let mut result = Err(LayoutError::NoConstructor);
+        self.do_and_recompute(|wm| {
+            let mut clients = BTreeMap::new();
+            let imported = wm.layout.import(exported, |id, data| {
+                if let Some(client) = data.client {
+                    clients.insert(id, client);
+                }
+                WindowData {
+                    client: None,
+                    decorations: make_decorations(display, root_window),
+                    template,
+                }
+            });
+            let Imported {
+                root,
+                windows,
+                actions,
+            } = match imported {
+                Ok(imported) => imported,
+                Err(e) => {
+                    result = Err(e);
+                    return vec![];
+                }
+            };
+            for (id, window) in clients {
+                let Some(old_idx) = wm.client_window_to_item_idx.get(&window).copied() else {
+                    continue;
+                };
+                let client = wm
+                    .layout
+                    .try_window_data_mut(old_idx)
+                    .unwrap()
+                    .client
+                    .take();
+                let w_idx = windows[&id];
+                wm.layout.try_window_data_mut(w_idx).unwrap().client = client;
+                wm.client_window_to_item_idx.insert(window, w_idx);
+            }
+            result = Ok(root);
+            actions
+        });
+        if let Ok(root) = result {
+            self.set_subtree_mapped(ItemIdx::Container(root), self.is_root_visible(root));
+        }
+        result
+    }
+
+    /// Move `item` to the end of `root`.
+    unsafe fn send_to_root(&mut self, item: ItemIdx, root: usize) -> Result<(), LayoutError> {
+        self.relocate(item, |layout| layout.checked_move_to_root(item, root))
+    }
+
+    /// Bring `item` out of the scratchpad, and give it the point.
+    unsafe fn summon(&mut self, item: ItemIdx, how: Summon) -> Result<(), LayoutError> {
+        match how {
+            Summon::Float => {
+                let float_root = self.float_root();
+                self.send_to_root(item, float_root)?;
+            }
+            Summon::Tile(cursor) => {
+                self.relocate(item, |layout| layout.checked_move(item, cursor))?
+            }
+        }
+        self.do_and_recompute(|wm| {
+            wm.point = item;
+            None
+        });
+        Ok(())
+    }
+
     fn compute_and_set_strut(&mut self) -> bool {
         let mut meet = StrutPartial::default();
         for x in self.struts_frontier.frontier().iter() {
@@ -778,17 +1507,21 @@ impl WmState {
         }
     }
 
-    pub fn supports_wm_delete(&self, window: x11::xlib::Window) -> bool {
+    /// Whether the client lists `protocol` in its `WM_PROTOCOLS` property.
+    pub fn supports_wm_protocol(&self, window: x11::xlib::Window, protocol: Atom) -> bool {
         let mut atoms = null_mut();
         let mut count = 0;
         let status = unsafe { XGetWMProtocols(self.display, window, &mut atoms, &mut count) };
-        assert_ne!(status, 0);
-        // Let RAII handle freeing the atoms
-        let boxed = unsafe {
-            let slice = std::slice::from_raw_parts_mut(atoms, count.try_into().unwrap());
-            Box::from_raw(slice)
-        };
-        boxed.iter().any(|&atom| atom == self.delete_window_atom)
+        if status == 0 || atoms.is_null() {
+            // No WM_PROTOCOLS property at all.
+            return false;
+        }
+        unsafe {
+            let slice = std::slice::from_raw_parts(atoms, count.try_into().unwrap());
+            let supported = slice.contains(&protocol);
+            XFree(atoms as *mut c_void);
+            supported
+        }
     }
 
     pub fn do_and_recompute<I, F>(&mut self, closure: F)
@@ -796,19 +1529,132 @@ impl WmState {
         I: IntoIterator<Item = LayoutAction<WindowData, ContainerData>>,
         F: FnOnce(&mut Self) -> I,
     {
+        if self.recompute_depth == 0 {
+            self.history.track_roots(&self.layout);
+        }
+        self.recompute_depth += 1;
         let old_point = self.point;
         let old_cursor = self.cursor;
         let actions = closure(self);
         let new_point = self.point;
         let new_cursor = self.cursor;
+        if self.layout.exists(new_point) {
+            self.layout.note_focused(new_point);
+        }
 
         for action in actions {
             info!("Running action: {:?}", action);
+            self.touched.push(action.item());
+            publish_event(Event::LayoutChanged {
+                action: action.without_data(),
+            });
             self.update_for_action(action);
         }
         unsafe {
             self.update_point_and_cursor(old_point, new_point, old_cursor, new_cursor);
         }
+        for (client, slot) in std::mem::take(&mut self.orphans) {
+            unsafe {
+                self.rehome_client(client, slot);
+            }
+        }
+        self.recompute_depth -= 1;
+        if self.recompute_depth == 0 {
+            // Changing a lone child's strategy moves nothing, so the point's roots count too.
+            let touched = std::mem::take(&mut self.touched);
+            self.history.record(
+                &self.layout,
+                touched.into_iter().chain([old_point, new_point]),
+            );
+        }
+    }
+    /// Like `do_and_recompute`, for a `closure` that can fail before it changes anything,
+    /// e.g. by calling one of the layout's `checked_*` methods.
+    pub fn try_do_and_recompute<I, E, F>(&mut self, closure: F) -> Result<(), E>
+    where
+        I: IntoIterator<Item = LayoutAction<WindowData, ContainerData>>,
+        F: FnOnce(&mut Self) -> Result<I, E>,
+    {
+        let mut result = Ok(());
+        self.do_and_recompute(|wm| {
+            closure(wm)
+                .map_err(|e| result = Err(e))
+                .into_iter()
+                .flatten()
+        });
+        result
+    }
+    /// Restore `root` to the shape it had before its last change (or after its last undone change, if `redo`).
+    /// Clients stay in their slots where those still exist, and are re-homed otherwise.
+    /// Recreated slots take back the clients they held, if those are still around.
+    unsafe fn step_history(&mut self, root: usize, redo: bool) {
+        let Some(snapshot) = self.history.step(root, redo) else {
+            info!(
+                "Nothing to {} in root {}",
+                if redo { "redo" } else { "undo" },
+                root
+            );
+            return;
+        };
+        let (display, x_root) = (self.display, self.root);
+        let template = WindowDecorationsTemplate::from_one(&self.theme.window);
+        self.do_and_recompute(|wm| {
+            let orphaned_from = &mut wm.orphaned_from;
+            let mut reclaimed = vec![];
+            let mut actions = wm.layout.restore(&snapshot, |w_idx| {
+                reclaimed.extend(orphaned_from.remove(&w_idx).map(|window| (w_idx, window)));
+                WindowData {
+                    client: None,
+                    decorations: make_decorations(display, x_root),
+                    template,
+                }
+            });
+            for (w_idx, window) in reclaimed {
+                let Some(old_idx) = wm.client_window_to_item_idx.get(&window).copied() else {
+                    continue;
+                };
+                let client = if wm.layout.exists(ItemIdx::Window(old_idx)) {
+                    let client = wm
+                        .layout
+                        .try_window_data_mut(old_idx)
+                        .unwrap()
+                        .client
+                        .take();
+                    // The scratch slot was only made to park the client, so drop it too.
+                    let old_root = wm.layout.root_of(ItemIdx::Window(old_idx));
+                    if old_root.is_some() && old_root == wm.scratch_root && old_root != Some(root) {
+                        actions.extend(wm.layout.destroy(ItemIdx::Window(old_idx)));
+                    }
+                    client
+                } else {
+                    // The restore itself just destroyed the slot it was in.
+                    actions.iter_mut().find_map(|action| match action {
+                        LayoutAction::ItemDestroyed {
+                            item: ItemAndData::Window(idx, data),
+                        } if *idx == old_idx => data.client.take(),
+                        _ => None,
+                    })
+                };
+                if let Some(client) = client {
+                    wm.layout.try_window_data_mut(w_idx).unwrap().client = Some(client);
+                    wm.client_window_to_item_idx.insert(window, w_idx);
+                }
+            }
+            if !wm.layout.exists(wm.point) {
+                wm.point = wm
+                    .layout
+                    .last_focused(root)
+                    .unwrap_or_else(|| wm.layout.topological_last(root));
+            }
+            if let Some(cursor) = wm.cursor {
+                if !wm.layout.is_cursor_valid(cursor) {
+                    wm.cursor = None;
+                }
+            }
+            actions
+        });
+        self.set_subtree_mapped(ItemIdx::Container(root), self.is_root_visible(root));
+        self.raise_float_layer();
     }
     pub fn update_for_action(&mut self, action: LayoutAction<WindowData, ContainerData>) {
         match action {
@@ -831,7 +1677,7 @@ impl WmState {
                                 bounds,
                                 &data.decorations,
                                 &data.template,
-                                Some(bounds.position.root_ctr) == self.displayed_root,
+                                self.is_root_visible(bounds.position.root_ctr),
                             );
                         }
                     }
@@ -844,7 +1690,7 @@ impl WmState {
                             bounds,
                             &data.decorations,
                             &data.template,
-                            Some(bounds.position.root_ctr) == self.displayed_root,
+                            self.is_root_visible(bounds.position.root_ctr),
                         );
                     }
                 }
@@ -863,20 +1709,21 @@ impl WmState {
                     self.cursor = None;
                 }
                 match item {
-                    ItemAndData::Window(_, data) => unsafe {
+                    ItemAndData::Window(idx, data) => unsafe {
                         if let Some(client) = data.client {
-                            self.kill_window(client.window);
+                            self.orphaned_from.insert(idx, client.window);
+                            self.orphan_client(client, ItemIdx::Window(idx));
                         }
-                        self.kill_window(data.decorations.down);
-                        self.kill_window(data.decorations.up);
-                        self.kill_window(data.decorations.right);
-                        self.kill_window(data.decorations.left);
+                        self.destroy_window(data.decorations.down);
+                        self.destroy_window(data.decorations.up);
+                        self.destroy_window(data.decorations.right);
+                        self.destroy_window(data.decorations.left);
                     },
                     ItemAndData::Container(_, data) => unsafe {
-                        self.kill_window(data.decorations.down);
-                        self.kill_window(data.decorations.up);
-                        self.kill_window(data.decorations.right);
-                        self.kill_window(data.decorations.left);
+                        self.destroy_window(data.decorations.down);
+                        self.destroy_window(data.decorations.up);
+                        self.destroy_window(data.decorations.right);
+                        self.destroy_window(data.decorations.left);
                     },
                 };
             }
@@ -1032,20 +1879,29 @@ impl std::fmt::Display for KeyCombo {
     }
 }
 
-static mut KEY_COMBO_TYPE: SCM = SCM_UNSPECIFIED;
-static mut WM_STATE_TYPE: SCM = SCM_UNSPECIFIED;
+static KEY_COMBO: ForeignType<KeyCombo> = ForeignType::new(c"key-combo");
+/// Only ever lent, for the length of a call into Scheme.
+static WM_STATE: ForeignType<WmState> = ForeignType::new(c"wm-state");
+
+impl ForeignObject for KeyCombo {
+    fn foreign_type() -> &'static ForeignType<Self> {
+        &KEY_COMBO
+    }
+}
 
-unsafe extern "C" fn write_key_combo(kc: SCM) -> SCM {
-    let s = format!("{}", get_foreign_object::<KeyCombo>(KEY_COMBO_TYPE, kc));
-    scm_from_utf8_stringn(std::mem::transmute(s.as_ptr()), s.len() as u64)
+impl ForeignObject for WmState {
+    fn foreign_type() -> &'static ForeignType<Self> {
+        &WM_STATE
+    }
 }
 
-unsafe extern "C" fn parse_key_combo(code_string: SCM) -> SCM {
-    let mut len: size_t = 0;
-    let s = scm_to_utf8_stringn(code_string, &mut len) as *mut u8;
-    let len = len.try_into().unwrap();
-    let s = String::from_raw_parts(s, len, len);
+#[scheme_fn("fwm-write-key-combo")]
+fn write_key_combo(kc: &KeyCombo) -> Result<String, BindingError> {
+    Ok(kc.to_string())
+}
 
+#[scheme_fn("fwm-parse-key-combo")]
+unsafe fn parse_key_combo(s: String) -> Result<Foreign<KeyCombo>, BindingError> {
     let mut ks = None;
     let mut shift = false;
     let mut lock = false;
@@ -1067,16 +1923,24 @@ unsafe extern "C" fn parse_key_combo(code_string: SCM) -> SCM {
             "mod4" => mod4 = true,
             "mod5" => mod5 = true,
             part => {
-                let part = CString::new(part).expect("XXX: Return error to scheme");
-                match XStringToKeysym(part.as_ptr()) {
-                    0 => panic!("XXX"),
+                let c_part = CString::new(part).map_err(|_| {
+                    BindingError::Invalid(format!("Key name {:?} contains a nul byte", part))
+                })?;
+                match XStringToKeysym(c_part.as_ptr()) {
+                    0 => {
+                        return Err(BindingError::Invalid(format!(
+                            "Unknown key name {:?}",
+                            part
+                        )))
+                    }
                     sym => ks = Some(sym),
                 }
             }
         }
     }
 
-    let ks = ks.expect("XXX");
+    let ks =
+        ks.ok_or_else(|| BindingError::Invalid(format!("Key combo {:?} doesn't name a key", s)))?;
     let combo = KeyCombo {
         key_sym: ks,
         shift,
@@ -1089,39 +1953,7 @@ unsafe extern "C" fn parse_key_combo(code_string: SCM) -> SCM {
         mod5,
     };
 
-    make_foreign_object(combo, b"KeyCombo\0", KEY_COMBO_TYPE)
-}
-
-struct LifetimeScm<'a> {
-    inner: SCM,
-    marker: PhantomData<&'a mut ()>,
-}
-
-unsafe fn make_foreign_object_from_ref<'a, T: Send>(
-    obj: &'a mut T,
-    r#type: SCM,
-) -> LifetimeScm<'a> {
-    let inner = scm_make_foreign_object_1(r#type, (obj as *mut T) as *mut c_void);
-    LifetimeScm {
-        inner,
-        marker: Default::default(),
-    }
-}
-
-unsafe fn make_foreign_object<T: Send>(obj: T, name: &[u8], r#type: SCM) -> SCM {
-    let storage = scm_gc_malloc_pointerless(
-        size_of::<T>() as u64,
-        CStr::from_bytes_with_nul(name).unwrap().as_ptr(),
-    ) as *mut T;
-    std::ptr::write(storage, obj);
-    scm_make_foreign_object_1(r#type, storage as *mut c_void)
-}
-
-unsafe fn get_foreign_object<'a, T>(obj: SCM, r#type: SCM) -> &'a mut T {
-    scm_assert_foreign_object_type(r#type, obj);
-    let p = scm_foreign_object_ref(obj, 0) as *mut T;
-    p.as_mut()
-        .expect("We should have set data in the constructor")
+    Ok(Foreign(combo))
 }
 
 unsafe extern "C" fn x_err(_display: *mut Display, ev: *mut XErrorEvent) -> i32 {
@@ -1137,10 +1969,213 @@ unsafe extern "C" fn x_io_err(_display: *mut Display) -> i32 {
 
 const XLIB_CONN: Token = Token(0);
 const FEEDBACK: Token = Token(1);
+const INIT_CHANGED: Token = Token(2);
 
 use mio::unix::pipe::Sender as MioSender;
 
 static FEEDBACK_TX: once_cell::sync::OnceCell<MioSender> = once_cell::sync::OnceCell::new();
+static MAIN_THREAD: once_cell::sync::OnceCell<ThreadId> = once_cell::sync::OnceCell::new();
+
+/// Something that happened in the WM, for `fwm-wait-event` and the
+/// event socket, which sends each as a line of JSON like
+/// `{"event":"point-changed","point":{"Window":3}}`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event {
+    PointChanged {
+        point: ItemIdx,
+    },
+    /// Input focus went to the client in `focused`, or to no client.
+    FocusChanged {
+        focused: Option<ItemIdx>,
+    },
+    ClientMapped {
+        point: ItemIdx,
+        window: Window,
+    },
+    ClientDestroyed {
+        point: ItemIdx,
+        window: Window,
+    },
+    RootShown {
+        root: Option<usize>,
+    },
+    /// One of the changes made by a layout operation.
+    LayoutChanged {
+        action: LayoutAction<(), ()>,
+    },
+    /// The key bindings were replaced, e.g. by `fwm-reconfigure`.
+    BindingsChanged {
+        bindings: Vec<String>,
+    },
+}
+
+impl Event {
+    /// The name of this kind of event, like `point-changed`.
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::PointChanged { .. } => "point-changed",
+            Event::FocusChanged { .. } => "focus-changed",
+            Event::ClientMapped { .. } => "client-mapped",
+            Event::ClientDestroyed { .. } => "client-destroyed",
+            Event::RootShown { .. } => "root-shown",
+            Event::LayoutChanged { .. } => "layout-changed",
+            Event::BindingsChanged { .. } => "bindings-changed",
+        }
+    }
+}
+
+static EVENT_SUBSCRIBERS: Mutex<Vec<mpsc::SyncSender<Event>>> = Mutex::new(Vec::new());
+
+/// How many events a subscriber can fall behind by before it's dropped.
+const EVENT_QUEUE_LEN: usize = 1024;
+
+/// A channel of every event from now on. Subscribers are dropped the first
+/// time an event can't be sent to them, so hanging up unsubscribes, and one
+/// that stops reading can't make events pile up without bound.
+fn subscribe_events() -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE_LEN);
+    EVENT_SUBSCRIBERS.lock().unwrap().push(tx);
+    rx
+}
+
+/// Never blocks: the main thread mustn't wait for subscribers.
+fn publish_event(event: Event) {
+    EVENT_SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|tx| match tx.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                warn!("Dropping an event subscriber that fell behind");
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        });
+}
+
+/// Send every event, as newline-delimited JSON, to each client that connects to `listener`.
+/// A client that falls `EVENT_QUEUE_LEN` events behind is disconnected.
+fn serve_events(listener: UnixListener) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Failed to accept an event subscriber: {}", e);
+                continue;
+            }
+        };
+        let events = subscribe_events();
+        std::thread::spawn(move || {
+            for event in events {
+                let mut line = serde_json::to_vec(&event).expect("events serialize to JSON");
+                line.push(b'\n');
+                if stream.write_all(&line).is_err() {
+                    // Hung up.
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// The `--init` file, which `fwm-reconfigure` evaluates again.
+struct InitFile {
+    path: String,
+    /// Whether to reconfigure whenever the file changes.
+    watch: bool,
+}
+
+static INIT_FILE: once_cell::sync::OnceCell<InitFile> = once_cell::sync::OnceCell::new();
+
+thread_local! {
+    /// Set while `load_init_config` evaluates the init file, whose `fwm-run-wm`
+    /// call then leaves its config in `RELOADED_CONFIG` instead of starting another WM.
+    static RELOADING: Cell<bool> = const { Cell::new(false) };
+    static RELOADED_CONFIG: Cell<Option<Rooted>> = const { Cell::new(None) };
+}
+
+/// Evaluate the init file again, for the config it passes to `fwm-run-wm`.
+/// All of its top-level forms run again, so anything in it that should only
+/// happen once, or state that should survive, must be guarded by the file
+/// itself, as the sample `init.ss` does with `define-once`.
+unsafe fn load_init_config() -> Result<Rooted, BindingError> {
+    let init = INIT_FILE
+        .get()
+        .ok_or_else(|| BindingError::Invalid("There is no init file to reload".to_string()))?;
+    let load = scm_variable_ref(scm_c_lookup(c"primitive-load".as_ptr()));
+    let path = init.path.serialize(serializer())?;
+    RELOADING.with(|reloading| reloading.set(true));
+    apply_logging_errors(load, &[path]);
+    RELOADING.with(|reloading| reloading.set(false));
+    RELOADED_CONFIG
+        .with(Cell::take)
+        .ok_or_else(|| BindingError::Invalid(format!("{} didn't call fwm-run-wm", init.path)))
+}
+
+/// An inotify watch for changes to the init file. It watches the file's
+/// directory, since editors often save by replacing the file.
+struct InitWatch {
+    fd: RawFd,
+    file_name: OsString,
+}
+
+impl InitWatch {
+    fn new(path: &Path) -> std::io::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file name to watch")
+            })?
+            .to_owned();
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let watch = InitWatch { fd, file_name };
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+            if libc::inotify_add_watch(fd, dir.as_ptr(), mask) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(watch)
+        }
+    }
+
+    /// Read all pending events, returning whether any of them were for the init file.
+    fn changed(&self) -> bool {
+        let mut buf = [0u8; 4096];
+        let mut changed = false;
+        loop {
+            let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            if n <= 0 {
+                return changed;
+            }
+            let mut offset = 0;
+            while offset < n as usize {
+                let event = unsafe {
+                    std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name_start = offset + size_of::<libc::inotify_event>();
+                offset = name_start + event.len as usize;
+                // Padded with nuls.
+                let name = buf[name_start..offset].split(|&b| b == 0).next();
+                changed |= name == Some(self.file_name.as_bytes());
+            }
+        }
+    }
+}
+
+impl Drop for InitWatch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
 
 #[derive(Debug, Default, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
 #[repr(C)]
@@ -1262,31 +2297,17 @@ unsafe fn get_strut(display: *mut Display, window: Window) -> Option<StrutPartia
     }
 }
 
-unsafe extern "C" fn run_wm(config: SCM) -> SCM {
-    let bindings = scm_assq_ref(
-        config,
-        scm_from_utf8_symbol(std::mem::transmute(b"bindings\0")),
-    );
-    let place_new_window = scm_assq_ref(
-        config,
-        scm_from_utf8_symbol(std::mem::transmute(b"place-new-window\0")),
-    );
-    let on_client_destroyed = scm_assq_ref(
-        config,
-        scm_from_utf8_symbol(std::mem::transmute(b"on-client-destroyed\0")),
-    );
-    let on_point_changed = ProtectedScm(scm_assq_ref(
-        config,
-        scm_from_utf8_symbol(std::mem::transmute(b"on-point-changed\0")),
-    ));
-    let on_button1_pressed = scm_assq_ref(
-        config,
-        scm_from_utf8_symbol(std::mem::transmute(b"on-button1-pressed\0")),
-    );
+#[scheme_fn("fwm-run-wm")]
+unsafe fn run_wm(config: SCM) -> Result<SCM, BindingError> {
+    if RELOADING.with(Cell::get) {
+        RELOADED_CONFIG.with(|reloaded| reloaded.set(Some(Rooted::new(config))));
+        return Ok(SCM_UNSPECIFIED);
+    }
     let after_start = scm_assq_ref(
         config,
         scm_from_utf8_symbol(std::mem::transmute(b"after-start\0")),
     );
+    let config = Config::parse(config)?;
     let display = XOpenDisplay(null());
     assert!(!display.is_null());
     XSetErrorHandler(Some(x_err));
@@ -1309,7 +2330,7 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
         height: screen.height.try_into().unwrap(),
     };
 
-    let mut wm = WmState::new(display, root, root_size, on_point_changed);
+    let mut wm = WmState::new(display, root, root_size, config);
     wm.root_size = root_size;
 
     // wm.do_and_recompute(|wm| {
@@ -1326,15 +2347,13 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
     //     })
     // });
 
-    let wm_scm = make_foreign_object_from_ref(&mut wm, WM_STATE_TYPE);
-
-    insert_bindings(wm_scm.inner, bindings);
-
     // XGrabServer(display);
     // ... rehome windows ...
     // XUngrabServer(display);
 
     let on_destroy = |wm: &mut WmState, window| {
+        wm.pending_kills.remove(&window);
+        wm.orphaned_from.retain(|_, client| *client != window);
         if let Entry::Occupied(oe) = wm.client_window_to_item_idx.entry(window) {
             let idx = oe.remove();
             if wm.layout.exists(ItemIdx::Window(idx)) {
@@ -1344,10 +2363,14 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                     .unwrap_window()
                     .client = None;
             }
-            let point = ItemIdx::Window(idx)
-                .serialize(Serializer::default())
-                .expect("XXX");
-            scm_apply_2(on_client_destroyed, wm_scm.inner, point, SCM_EOL);
+            publish_event(Event::ClientDestroyed {
+                point: ItemIdx::Window(idx),
+                window,
+            });
+            let hook = wm.hooks.on_client_destroyed.get();
+            wm.call_hook::<IgnoredAny>("on-client-destroyed", hook, || {
+                Ok(vec![ItemIdx::Window(idx).serialize(serializer())?])
+            });
         }
         if wm.clear_strut(window) {
             wm.do_resize()
@@ -1355,7 +2378,8 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
     };
     let (feedback_tx, mut feedback_rx) = mio::unix::pipe::new().unwrap();
     FEEDBACK_TX.set(feedback_tx).expect("already ran run_wm!");
-    do_on_main_thread(after_start);
+    MAIN_THREAD.set(std::thread::current().id()).unwrap();
+    do_on_main_thread(after_start)?;
 
     let display_fd = XConnectionNumber(display) as RawFd;
     let mut poll = Poll::new().unwrap();
@@ -1370,6 +2394,21 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
     poll.registry()
         .register(&mut feedback_rx, FEEDBACK, Interest::READABLE)
         .unwrap();
+    let init_watch = match INIT_FILE.get().filter(|init| init.watch) {
+        Some(init) => match InitWatch::new(Path::new(&init.path)) {
+            Ok(watch) => {
+                poll.registry()
+                    .register(&mut SourceFd(&watch.fd), INIT_CHANGED, Interest::READABLE)
+                    .unwrap();
+                Some(watch)
+            }
+            Err(e) => {
+                error!("Failed to watch {}: {}", init.path, e);
+                None
+            }
+        },
+        None => None,
+    };
 
     XGrabButton(
         display,
@@ -1385,12 +2424,32 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
     );
     loop {
         let mut e = MaybeUninit::<XEvent>::uninit();
-        while poll.poll(&mut events, None).is_err() {}
+        let timeout = wm.next_kill_timeout();
+        while poll.poll(&mut events, timeout).is_err() {}
+        wm.escalate_expired_kills();
         for mio_ev in &events {
             if mio_ev.token() == FEEDBACK {
-                while let Ok(f) = feedback_rx.read_u64::<NativeEndian>() {
-                    let f = f as SCM;
-                    scm_apply_1(f, wm_scm.inner, SCM_EOL);
+                while let Ok(call) = feedback_rx.read_u64::<NativeEndian>() {
+                    // Boxed by `send_to_main_thread`.
+                    let MainThreadCall { f, reply } = *Box::from_raw(call as *mut MainThreadCall);
+                    match reply {
+                        Some(reply) => {
+                            let result =
+                                WM_STATE.lend(&mut wm, |scm| apply_catching(f.get(), &[scm]));
+                            // The caller may have given up waiting.
+                            let _ = reply.send(result.map(Rooted::new));
+                        }
+                        None => {
+                            WM_STATE.lend(&mut wm, |scm| apply_logging_errors(f.get(), &[scm]));
+                        }
+                    }
+                }
+            }
+            if mio_ev.token() == INIT_CHANGED && init_watch.as_ref().is_some_and(InitWatch::changed)
+            {
+                match load_init_config().and_then(|config| Config::parse(config.get())) {
+                    Ok(config) => wm.reconfigure(config),
+                    Err(e) => error!("Failed to reconfigure: {}", e),
                 }
             }
         }
@@ -1405,19 +2464,24 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
 
                     let combo = KeyCombo::from_x(keysym, state);
                     info!("received key combo: {:?}", combo);
-                    let binding = {
-                        let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
-                        wm.bindings.get(&combo)
-                    };
-                    if let Some(ProtectedScm(proc)) = binding {
+                    // Cloned, since the binding may clear the bindings.
+                    let binding = wm.bindings.get(&combo).cloned();
+                    if let Some(proc) = binding {
                         info!("binding found, calling into scheme");
-                        scm_apply_1(*proc, wm_scm.inner, SCM_EOL);
+                        WM_STATE.lend(&mut wm, |scm| apply_logging_errors(proc.get(), &[scm]));
                     } else {
                         info!("No binding found");
                     };
                 }
                 x11::xlib::ClientMessage => {
-                    let XClientMessageEvent { message_type, .. } = e.client_message;
+                    let XClientMessageEvent {
+                        message_type, data, ..
+                    } = e.client_message;
+                    if message_type == wm.protocols_atom && data.get_long(0) as Atom == wm.ping_atom
+                    {
+                        // A pong: the client echoes its own window ID in the third slot.
+                        wm.record_pong(data.get_long(2) as Window);
+                    }
                     let p_message_type = XGetAtomName(display, message_type);
                     if p_message_type != null_mut() {
                         let message_type = CStr::from_ptr(p_message_type);
@@ -1434,30 +2498,32 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                         ..
                     } = e.button;
                     let point = {
-                        let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
-                        if let Some(displayed_root) = wm.displayed_root {
-                            let position = Position {
-                                x: x_root as usize,
-                                y: y_root as usize,
-                                root_ctr: displayed_root,
-                            };
-                            let w_idx = wm.layout.window_at(position);
-                            let point = w_idx.map(|w_idx| ItemIdx::Window(w_idx));
-                            info!(
-                                "Button pressed at position {:?}, corresponding point {:?}",
-                                position, point
-                            );
-                            point
-                        } else {
-                            None
-                        }
+                        // The floating layer is on top, so it gets first dibs on the click.
+                        let float_root = wm
+                            .float_root
+                            .filter(|&float_root| wm.is_root_visible(float_root));
+                        float_root
+                            .into_iter()
+                            .chain(wm.displayed_root)
+                            .find_map(|root_ctr| {
+                                let position = Position {
+                                    x: x_root as usize,
+                                    y: y_root as usize,
+                                    root_ctr,
+                                };
+                                let w_idx = wm.layout.window_at(position);
+                                let point = w_idx.map(|w_idx| ItemIdx::Window(w_idx));
+                                info!(
+                                    "Button pressed at position {:?}, corresponding point {:?}",
+                                    position, point
+                                );
+                                point
+                            })
                     };
-                    scm_apply_2(
-                        on_button1_pressed,
-                        wm_scm.inner,
-                        point.serialize(Serializer::default()).unwrap(),
-                        SCM_EOL,
-                    );
+                    let hook = wm.hooks.on_button1_pressed.get();
+                    wm.call_hook::<IgnoredAny>("on-button1-pressed", hook, || {
+                        Ok(vec![point.serialize(serializer())?])
+                    });
                     // https://stackoverflow.com/questions/46288251/capture-button-events-in-xlib-then-passing-the-event-to-the-client
                     XAllowEvents(display, ReplayPointer, time);
                     XSync(display, 0);
@@ -1465,7 +2531,6 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                 x11::xlib::ConfigureRequest => {
                     // Let windows do whatever they want if we haven't taken them over yet.
                     let ev = e.configure_request;
-                    let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
                     match wm.client_window_to_item_idx.get(&ev.window).copied() {
                         None => {
                             let mut changes = XWindowChanges {
@@ -1531,7 +2596,6 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                     } = e.configure;
 
                     if window == root {
-                        let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
                         // let new_bounds = WindowBounds {
                         //     position: Default::default(),
                         //     content: AreaSize {
@@ -1556,82 +2620,22 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                 x11::xlib::MapRequest => {
                     let XMapRequestEvent { window, .. } = e.map_request;
 
-                    let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
                     let already_mapped = wm.client_window_to_item_idx.contains_key(&window);
 
                     let is_dock = is_dock(display, window);
                     info!("is_dock: {}", is_dock);
                     if !already_mapped && !is_dock {
-                        let insert_cursor = scm_apply_1(place_new_window, wm_scm.inner, SCM_EOL);
-                        let insert_cursor =
-                            MoveOrReplace::deserialize(Deserializer { scm: insert_cursor })
-                                .expect("XXX");
-                        wm.do_and_recompute(|wm| match insert_cursor {
-                            MoveOrReplace::Move(insert_cursor) => {
-                                let root_ctr =
-                                    wm.layout.bounds(insert_cursor.item()).position.root_ctr;
-                                let decorations = make_decorations(display, root);
-                                let w_idx = wm.layout.alloc_window(
-                                    WindowData {
-                                        client: Some(X11ClientWindowData {
-                                            window,
-                                            map_wanted: true,
-                                            // mapped: false,
-                                        }),
-                                        decorations,
-                                        template: BASIC_DECO,
-                                    },
-                                    root_ctr,
-                                );
-                                wm.client_window_to_item_idx.insert(window, w_idx);
-                                let actions =
-                                    wm.layout.r#move(ItemIdx::Window(w_idx), insert_cursor);
-                                wm.point = ItemIdx::Window(w_idx);
-                                XRaiseWindow(wm.display, window);
-                                actions
-                            }
-                            // XXX is it actually right to destroy the
-                            MoveOrReplace::Replace(ItemIdx::Window(w_idx)) => {
-                                let old_bounds = wm.layout.bounds(ItemIdx::Window(w_idx));
-
-                                wm.client_window_to_item_idx.insert(window, w_idx);
-                                wm.point = ItemIdx::Window(w_idx);
-                                let old_client = std::mem::replace(
-                                    &mut wm
-                                        .layout
-                                        .try_data_mut(wm.point)
-                                        .unwrap()
-                                        .unwrap_window()
-                                        .client,
-                                    Some(X11ClientWindowData {
-                                        window,
-                                        map_wanted: true,
-                                    }),
-                                );
-                                XRaiseWindow(wm.display, window);
-                                if let Some(X11ClientWindowData {
-                                    window,
-                                    map_wanted: _,
-                                }) = old_client
-                                {
-                                    XDestroyWindow(wm.display, window);
-                                }
-                                vec![LayoutAction::NewBounds {
-                                    idx: wm.point,
-                                    bounds: old_bounds,
-                                }]
+                        let hook = wm.hooks.place_new_window.get();
+                        match wm.call_hook("place-new-window", hook, || Ok(vec![])) {
+                            Some(placement) => wm.manage_client(window, placement, true),
+                            None => {
+                                let placement = wm.scratch_placement();
+                                wm.manage_client(window, placement, false)
                             }
-                            MoveOrReplace::Replace(ItemIdx::Container(_c_idx)) => todo!(),
-                        });
-                        if Some(wm.layout.bounds(insert_cursor.item()).position.root_ctr)
-                            == wm.displayed_root
-                        {
-                            XMapWindow(display, window);
                         }
                     }
                 }
                 x11::xlib::MapNotify => {
-                    let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
                     let ev = e.map;
                     if let Some(&idx) = wm.client_window_to_item_idx.get(&ev.window) {
                         if let Some(WindowData { client, .. }) = wm.layout.try_window_data_mut(idx)
@@ -1654,7 +2658,6 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                     wm.ensure_focus();
                 }
                 x11::xlib::UnmapNotify => {
-                    let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
                     let ev = e.unmap;
                     if let Some(w_idx) = wm.client_window_to_item_idx.get(&ev.window) {
                         if let Some(client) = wm
@@ -1669,8 +2672,7 @@ unsafe extern "C" fn run_wm(config: SCM) -> SCM {
                 }
                 x11::xlib::DestroyNotify => {
                     let XDestroyWindowEvent { window, .. } = e.destroy_window;
-                    let wm = get_foreign_object::<WmState>(wm_scm.inner, WM_STATE_TYPE);
-                    on_destroy(wm, window);
+                    on_destroy(&mut wm, window);
                 }
                 _ => {}
             }
@@ -1685,14 +2687,14 @@ enum SpatialDir {
     Child,
 }
 
-unsafe extern "C" fn navigate(state: SCM, dir: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    match SpatialDir::deserialize(Deserializer { scm: dir }).expect("XXX") {
+#[scheme_fn("fwm-navigate")]
+fn navigate(wm: &mut WmState, dir: SpatialDir) -> Result<(), BindingError> {
+    match dir {
         SpatialDir::Planar(dir) => {
             wm.navigate(dir);
         }
         SpatialDir::Parent => {
-            if let Some(parent_ctr) = wm.layout.parent_container(wm.point) {
+            if let Some(parent_ctr) = wm.layout.checked_parent_container(wm.point)? {
                 wm.do_and_recompute(|wm| {
                     wm.point = ItemIdx::Container(parent_ctr);
                     None
@@ -1711,86 +2713,185 @@ unsafe extern "C" fn navigate(state: SCM, dir: SCM) -> SCM {
             }
         }
     }
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn cursor(state: SCM, dir: SCM) -> SCM {
-    let dir = match SpatialDir::deserialize(Deserializer { scm: dir }).expect("XXX") {
+#[scheme_fn("fwm-cursor")]
+fn cursor(wm: &mut WmState, dir: SpatialDir) -> Result<(), BindingError> {
+    let dir = match dir {
         SpatialDir::Planar(dir) => dir,
-        _ => todo!(),
+        _ => {
+            return Err(BindingError::Invalid(
+                "The cursor can only move in a planar direction".to_string(),
+            ))
+        }
     };
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
     wm.navigate_cursor(dir);
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn get_point(state: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    wm.point.serialize(Serializer::default()).expect("XXX")
+#[scheme_fn("fwm-get-point")]
+fn get_point(wm: &mut WmState) -> Result<ItemIdx, BindingError> {
+    Ok(wm.point)
 }
 
-unsafe extern "C" fn set_point(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
+#[scheme_fn("fwm-set-point")]
+fn set_point(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
+    wm.layout.check_item(point)?;
     wm.do_and_recompute(|wm| {
         wm.point = point;
         None
     });
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn get_cursor(state: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    wm.cursor.serialize(Serializer::default()).expect("XXX")
+#[scheme_fn("fwm-get-cursor")]
+fn get_cursor(wm: &mut WmState) -> Result<Option<MoveCursor>, BindingError> {
+    Ok(wm.cursor)
 }
 
-unsafe extern "C" fn set_cursor(state: SCM, cursor: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let cursor = Option::<MoveCursor>::deserialize(Deserializer { scm: cursor }).expect("XXX");
+#[scheme_fn("fwm-set-cursor")]
+fn set_cursor(wm: &mut WmState, cursor: Option<MoveCursor>) -> Result<(), BindingError> {
     if let Some(cursor) = cursor {
-        assert!(wm.layout.is_cursor_valid(cursor), "XXX");
+        wm.layout.check_cursor(cursor)?;
     }
     wm.do_and_recompute(|wm| {
         wm.cursor = cursor;
         None
     });
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe fn scm_from_bool(x: bool) -> SCM {
-    if x {
-        SCM_BOOL_T
-    } else {
-        SCM_BOOL_F
+/// How values are handed to Scheme. Structs, and enum variants with payloads,
+/// are records; `scheme_setup` defines their accessors and predicates.
+const SCHEME_CONFIG: SerializerConfig = SerializerConfig {
+    options: OptionRepr::List,
+    structs: StructRepr::Record,
+    enums: EnumRepr::Record,
+    maps_as_hash_tables: false,
+};
+
+fn serializer() -> Serializer {
+    Serializer::new(SCHEME_CONFIG)
+}
+
+/// Why a binding failed; `btv_scheme::guard` raises it as a Scheme exception.
+#[derive(Debug)]
+enum BindingError {
+    /// An argument didn't deserialize to the expected type.
+    Scheme(btv_scheme::Error),
+    Layout(LayoutError),
+    /// Any other bad argument.
+    Invalid(String),
+}
+
+impl From<btv_scheme::Error> for BindingError {
+    fn from(e: btv_scheme::Error) -> Self {
+        Self::Scheme(e)
+    }
+}
+
+impl From<LayoutError> for BindingError {
+    fn from(e: LayoutError) -> Self {
+        Self::Layout(e)
+    }
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingError::Scheme(e) => write!(f, "{}", e),
+            BindingError::Layout(e) => write!(f, "{}", e),
+            BindingError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// The exception's key is `wrong-type-arg` for arguments that don't deserialize,
+/// `layout-error` for `LayoutError`s, and `misc-error` for other bad arguments.
+impl SchemeFnError for BindingError {
+    const CONFIG: SerializerConfig = SCHEME_CONFIG;
+
+    fn key(&self) -> &'static CStr {
+        match self {
+            BindingError::Scheme(_) => c"wrong-type-arg",
+            BindingError::Layout(_) => c"layout-error",
+            BindingError::Invalid(_) => c"misc-error",
+        }
+    }
+}
+
+/// Apply `proc` to `args`, returning a description of any Scheme exception
+/// instead of propagating it.
+unsafe fn apply_catching(proc: SCM, args: &[SCM]) -> Result<SCM, String> {
+    struct Call {
+        proc: SCM,
+        args: SCM,
     }
+    unsafe extern "C" fn body(data: *mut c_void) -> SCM {
+        let Call { proc, args } = *(data as *const Call);
+        scm_apply_0(proc, args)
+    }
+    unsafe extern "C" fn handler(data: *mut c_void, key: SCM, args: SCM) -> SCM {
+        let describe = |scm| {
+            try_scm_to_string_or_sym(scm_object_to_string(scm, SCM_UNDEFINED)).unwrap_or_default()
+        };
+        *(data as *mut Option<String>) = Some(format!("{} {}", describe(key), describe(args)));
+        SCM_UNSPECIFIED
+    }
+    let args = args
+        .iter()
+        .rev()
+        .fold(SCM_EOL, |tail, &arg| scm_cons(arg, tail));
+    let mut call = Call { proc, args };
+    let mut exception: Option<String> = None;
+    let result = scm_c_catch(
+        SCM_BOOL_T,
+        Some(body),
+        &mut call as *mut Call as *mut c_void,
+        Some(handler),
+        &mut exception as *mut Option<String> as *mut c_void,
+        None,
+        null_mut(),
+    );
+    match exception {
+        Some(exception) => Err(exception),
+        None => Ok(result),
+    }
+}
+
+/// Apply `proc` to `args`, logging any Scheme exception instead of propagating it,
+/// so that one broken binding or hook doesn't unwind out of the event loop.
+unsafe fn apply_logging_errors(proc: SCM, args: &[SCM]) -> SCM {
+    apply_catching(proc, args).unwrap_or_else(|exception| {
+        error!("Uncaught Scheme exception: {}", exception);
+        SCM_UNSPECIFIED
+    })
 }
 
-unsafe extern "C" fn is_occupied(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    scm_from_bool(match point {
+#[scheme_fn("fwm-occupied?")]
+fn is_occupied(wm: &mut WmState, point: ItemIdx) -> Result<bool, BindingError> {
+    wm.layout.check_item(point)?;
+    Ok(match point {
         ItemIdx::Container(_) => true, // Containers always count as occupied, since their frame is their entire content.
         ItemIdx::Window(w_idx) => wm
             .layout
             .try_window_data(w_idx)
-            .expect("XXX")
+            .expect("checked above")
             .client
             .is_some(),
     })
 }
 
-unsafe extern "C" fn nearest_container(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let ctr = wm.layout.nearest_container(point);
-    scm_from_uint64(ctr as u64)
+#[scheme_fn("fwm-nearest-container")]
+fn nearest_container(wm: &mut WmState, point: ItemIdx) -> Result<usize, BindingError> {
+    wm.layout.check_item(point)?;
+    Ok(wm.layout.nearest_container(point))
 }
 
-unsafe extern "C" fn n_children(state: SCM, ctr: SCM) -> SCM {
-    let ctr = scm_to_uint64(ctr).try_into().unwrap();
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let n = wm.layout.n_children(ItemIdx::Container(ctr));
-    scm_from_uint64(n as u64)
+#[scheme_fn("fwm-n-children")]
+fn n_children(wm: &mut WmState, ctr: usize) -> Result<usize, BindingError> {
+    Ok(wm.layout.checked_n_children(ItemIdx::Container(ctr))?)
 }
 
 #[derive(Deserialize, Serialize)]
@@ -1799,37 +2900,44 @@ enum MoveOrReplace {
     Replace(ItemIdx),
 }
 
-impl MoveOrReplace {
-    fn item(&self) -> ItemIdx {
-        match self {
-            Self::Move(move_cursor) => move_cursor.item(),
-            Self::Replace(item) => *item,
-        }
-    }
+/// How to bring an item out of the scratchpad.
+#[derive(Deserialize, Serialize)]
+enum Summon {
+    /// Show it in the floating layer, above the displayed root.
+    Float,
+    /// Tile it into the layout at the given cursor.
+    Tile(MoveCursor),
 }
 
-unsafe extern "C" fn make_cursor_into(container: SCM, index: SCM) -> SCM {
-    let container = scm_to_uint64(container).try_into().unwrap();
-    let index = scm_to_uint64(index).try_into().unwrap();
-    let cursor = MoveOrReplace::Move(MoveCursor::Into { container, index });
-    cursor.serialize(Serializer::default()).expect("XXX")
+/// What to do with a client whose slot was destroyed or replaced;
+/// returned by the `on-client-orphaned` hook.
+#[derive(Deserialize, Serialize)]
+enum OrphanAction {
+    /// Ask the client to close, via `WM_DELETE_WINDOW`.
+    Close,
+    /// Park the client, unmapped, at the end of the scratch root.
+    Scratch,
+    /// Give the client a new slot, like `place-new-window` does.
+    Place(MoveOrReplace),
 }
 
-unsafe extern "C" fn make_cursor_before(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
+#[scheme_fn("fwm-make-cursor-into")]
+fn make_cursor_into(container: usize, index: usize) -> Result<MoveOrReplace, BindingError> {
+    Ok(MoveOrReplace::Move(MoveCursor::Into { container, index }))
+}
 
-    let cursor = MoveOrReplace::Move(wm.layout.cursor_before(point));
-    cursor.serialize(Serializer::default()).expect("XXX")
+#[scheme_fn("fwm-make-cursor-before")]
+fn make_cursor_before(wm: &mut WmState, point: ItemIdx) -> Result<MoveOrReplace, BindingError> {
+    wm.layout.check_item(point)?;
+    Ok(MoveOrReplace::Move(wm.layout.cursor_before(point)))
 }
 
-unsafe extern "C" fn kill_item_at(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
+#[scheme_fn("fwm-kill-item-at")]
+fn kill_item_at(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
     info!("Killing item at {:?}", point);
-    wm.do_and_recompute(|wm| {
+    wm.try_do_and_recompute(|wm| {
         let topo_next = wm.layout.topological_next(wm.point);
-        let actions = wm.layout.destroy(point);
+        let actions = wm.layout.checked_destroy(point)?;
         if !wm.layout.exists(wm.point) {
             // XXX Point should be none!
             wm.point = topo_next
@@ -1839,224 +2947,458 @@ unsafe extern "C" fn kill_item_at(state: SCM, point: SCM) -> SCM {
                 })
                 .unwrap_or(ItemIdx::Container(0));
         }
-        actions
-    });
-    SCM_UNSPECIFIED
-}
-
-unsafe extern "C" fn request_kill_client_at(state: SCM, window: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let window = scm_to_uint64(window).try_into().unwrap();
-    if let Some(client) = wm.layout.try_window_data(window).expect("XXX").client {
-        if wm.supports_wm_delete(client.window) {
-            let mut cmd: ClientMessageData = Default::default();
-            cmd.set_long(0, wm.delete_window_atom.try_into().unwrap());
-            let client_message = XClientMessageEvent {
-                type_: ClientMessage,
-                serial: 0,
-                send_event: 0,
-                display: wm.display,
-                window: client.window,
-                message_type: wm.protocols_atom,
-                format: 32,
-                data: cmd,
-            };
-            let mut ev = XEvent { client_message };
-            XSendEvent(wm.display, client.window, 0, 0, &mut ev);
-        } else {
-            todo!()
-        }
+        Ok::<_, LayoutError>(actions)
+    })?;
+    Ok(())
+}
+
+#[scheme_fn("fwm-request-kill-client-at")]
+unsafe fn request_kill_client_at(wm: &mut WmState, window: usize) -> Result<(), BindingError> {
+    wm.layout.check_item(ItemIdx::Window(window))?;
+    if let Some(client) = wm
+        .layout
+        .try_window_data(window)
+        .expect("checked above")
+        .client
+    {
+        wm.request_close_client(client.window);
     }
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn new_window_at(state: SCM, cursor: SCM) -> SCM {
-    let cur = MoveOrReplace::deserialize(Deserializer { scm: cursor }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let root_ctr = wm.layout.bounds(cur.item()).position.root_ctr;
+#[scheme_fn("fwm-new-window-at")]
+unsafe fn new_window_at(wm: &mut WmState, cursor: MoveOrReplace) -> Result<(), BindingError> {
+    let cur = match cursor {
+        MoveOrReplace::Move(cur) => cur,
+        MoveOrReplace::Replace(_) => {
+            return Err(BindingError::Invalid(
+                "Replacing an item with a new window is not supported".to_string(),
+            ))
+        }
+    };
+    wm.layout.check_cursor(cur)?;
+    let root_ctr = wm.layout.checked_bounds(cur.item())?.position.root_ctr;
     let decorations = make_decorations(wm.display, wm.root);
-    let win = wm.layout.alloc_window(
+    let win = wm.layout.checked_alloc_window(
         WindowData {
             client: None,
             decorations,
-            template: BASIC_DECO,
+            template: WindowDecorationsTemplate::from_one(&wm.theme.window),
         },
         root_ctr,
-    );
-    match cur {
-        MoveOrReplace::Move(cur) => {
-            wm.do_and_recompute(|wm| wm.layout.r#move(ItemIdx::Window(win), cur));
-        }
-        MoveOrReplace::Replace(_) => todo!(),
+    )?;
+    wm.try_do_and_recompute(|wm| wm.layout.checked_move(ItemIdx::Window(win), cur))?;
+    Ok(())
+}
+
+/// The client at `point`, if there is one.
+fn client_at(wm: &WmState, point: ItemIdx) -> Option<Window> {
+    match wm.layout.try_data(point) {
+        Some(LayoutDataRef::Window(WindowData {
+            client: Some(client),
+            ..
+        })) => Some(client.window),
+        _ => None,
     }
-    SCM_UNSPECIFIED
 }
 
-unsafe extern "C" fn kill_client_at(state: SCM, point: SCM) -> SCM {
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    wm.do_and_recompute(|wm| {
-        if let Some(LayoutDataMut::Window(WindowData { client, .. })) =
-            wm.layout.try_data_mut(point)
-        {
-            if let Some(window) = client.take() {
-                wm.kill_window(window.window);
-            }
-        }
-        None
-    });
-    SCM_UNSPECIFIED
+/// Ask the client at `point` to close, escalating if it doesn't.
+/// The slot is emptied when the client's DestroyNotify arrives.
+#[scheme_fn("fwm-kill-client-at")]
+unsafe fn kill_client_at(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
+    if let Some(window) = client_at(wm, point) {
+        wm.request_close_client(window);
+    }
+    Ok(())
+}
+
+/// Disconnect the client at `point` with `XKillClient` right away, without
+/// asking it to close first. This closes all of that client's windows.
+#[scheme_fn("fwm-force-kill-client-at")]
+unsafe fn force_kill_client_at(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
+    if let Some(window) = client_at(wm, point) {
+        wm.force_kill_client(window);
+    }
+    Ok(())
 }
 
 const SCM_BOOL_F: SCM = 0x4 as SCM;
 const SCM_BOOL_T: SCM = 0x404 as SCM;
 
-unsafe extern "C" fn insert_bindings(state: SCM, mut bindings: SCM) -> SCM {
-    let state = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
+unsafe fn parse_bindings(mut bindings: SCM) -> Result<Vec<(KeyCombo, Rooted)>, BindingError> {
+    let mut parsed = vec![];
     while scm_is_pair(bindings) {
         let binding = scm_car_unchecked(bindings);
         bindings = scm_cdr_unchecked(bindings);
 
-        // XXX handle error
-        assert!(scm_is_pair(binding));
+        if !scm_is_pair(binding) {
+            return Err(BindingError::Invalid(
+                "Each binding must be a (key-combo . procedure) pair".to_string(),
+            ));
+        }
         let kc = scm_car_unchecked(binding);
         let proc = scm_cdr_unchecked(binding);
 
-        // XXX handle error
-        assert!(scm_is_truthy(scm_procedure_p(proc)));
-        let kc = get_foreign_object::<KeyCombo>(kc, KEY_COMBO_TYPE).clone();
-        state.bindings.insert(kc, ProtectedScm::new(proc));
-        XGrabKey(
-            state.display,
-            XKeysymToKeycode(state.display, kc.key_sym.into()).into(),
-            kc.x_modifiers(),
-            state.root,
-            0,
-            GrabModeAsync,
-            GrabModeAsync,
-        );
+        if !scm_is_truthy(scm_procedure_p(proc)) {
+            return Err(BindingError::Invalid(
+                "A binding's action must be a procedure".to_string(),
+            ));
+        }
+        let kc = KEY_COMBO.get(kc)?;
+        // Otherwise only the last binding of a combo would take effect.
+        if parsed.iter().any(|(bound, _)| *bound == kc) {
+            return Err(BindingError::Invalid(format!(
+                "{} is bound more than once",
+                kc
+            )));
+        }
+        parsed.push((kc, Rooted::new(proc)));
     }
-    SCM_UNSPECIFIED
+    Ok(parsed)
+}
+
+#[scheme_fn("fwm-clear-bindings")]
+unsafe fn clear_bindings(state: &mut WmState) -> Result<(), BindingError> {
+    state.ungrab_bindings();
+    state.announce_bindings();
+    Ok(())
+}
+
+/// Switch to a new config alist, like the one given to `fwm-run-wm`,
+/// without disturbing the layout. With no config, the init file is
+/// evaluated again, and the config it passes to `fwm-run-wm` is used.
+#[scheme_fn("fwm-reconfigure")]
+unsafe fn reconfigure(
+    wm: &mut WmState,
+    #[optional] config: Option<SCM>,
+) -> Result<(), BindingError> {
+    let config = match config {
+        Some(config) => Rooted::new(config),
+        None => load_init_config()?,
+    };
+    wm.reconfigure(Config::parse(config.get())?);
+    Ok(())
 }
 
-unsafe extern "C" fn clear_bindings(state: SCM) -> SCM {
-    let state = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    for (kc, _) in state.bindings.drain() {
-        XUngrabKey(
-            state.display,
-            XKeysymToKeycode(state.display, kc.key_sym.into()).into(),
-            kc.x_modifiers(),
-            state.root,
-        );
+/// `container`'s own gaps, as given to `fwm-set-gaps`, or an empty list
+/// if it uses the config's.
+#[scheme_fn("fwm-get-gaps")]
+fn get_gaps(wm: &mut WmState, container: usize) -> Result<Option<Gaps>, BindingError> {
+    wm.layout.check_item(ItemIdx::Container(container))?;
+    Ok(wm.layout.own_gaps(container))
+}
+
+/// Give `container` its own gaps, a record from `make-gaps`, or with
+/// an empty list the config's again. Outer gaps only apply to roots.
+#[scheme_fn("fwm-set-gaps")]
+fn set_gaps(wm: &mut WmState, container: usize, gaps: Option<Gaps>) -> Result<(), BindingError> {
+    wm.layout.check_item(ItemIdx::Container(container))?;
+    wm.do_and_recompute(|wm| wm.layout.set_gaps(container, gaps));
+    Ok(())
+}
+
+/// `container`'s own padding, as given to `fwm-set-padding`, or an empty
+/// list if it uses the config's.
+#[scheme_fn("fwm-get-padding")]
+fn get_padding(wm: &mut WmState, container: usize) -> Result<Option<Padding>, BindingError> {
+    wm.layout.check_item(ItemIdx::Container(container))?;
+    Ok(wm.layout.own_padding(container))
+}
+
+/// Give `container` its own padding, a record from `make-padding`, or with
+/// an empty list the config's again.
+#[scheme_fn("fwm-set-padding")]
+fn set_padding(
+    wm: &mut WmState,
+    container: usize,
+    padding: Option<Padding>,
+) -> Result<(), BindingError> {
+    wm.layout.check_item(ItemIdx::Container(container))?;
+    wm.do_and_recompute(|wm| wm.layout.set_padding(container, padding));
+    Ok(())
+}
+
+#[scheme_fn("fwm-get-root-margins")]
+fn get_root_margins(wm: &mut WmState, root: usize) -> Result<Padding, BindingError> {
+    wm.layout.check_root(root)?;
+    Ok(wm.layout.root_margins(root))
+}
+
+/// Leave space at `root`'s edges, on top of its padding, like for a bar
+/// or around a notch. `margins` is a record from `make-padding`.
+#[scheme_fn("fwm-set-root-margins")]
+fn set_root_margins(wm: &mut WmState, root: usize, margins: Padding) -> Result<(), BindingError> {
+    wm.layout.check_root(root)?;
+    wm.do_and_recompute(|wm| wm.layout.set_root_margins(root, margins));
+    Ok(())
+}
+
+/// What `fwm-export-layout` records about each window, and `fwm-import-layout` reads.
+#[derive(Serialize, Deserialize, Debug)]
+struct ExportedWindow {
+    /// The X window of the client in this slot, if any.
+    client: Option<Window>,
+    /// The client's `WM_NAME`.
+    title: Option<String>,
+}
+
+/// The client's `WM_NAME`, if it has one.
+unsafe fn window_title(display: *mut Display, window: Window) -> Option<String> {
+    let mut name: *mut c_char = null_mut();
+    if XFetchName(display, window, &mut name) == 0 || name.is_null() {
+        return None;
+    }
+    let title = CStr::from_ptr(name).to_string_lossy().into_owned();
+    XFree(name as *mut c_void);
+    Some(title)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Json,
+    Ron,
+}
+
+impl ExportFormat {
+    /// `'json`, the default, or `'ron`.
+    fn parse(format: Option<String>) -> Result<Self, BindingError> {
+        match format.as_deref() {
+            None | Some("json") => Ok(ExportFormat::Json),
+            Some("ron") => Ok(ExportFormat::Ron),
+            Some(other) => Err(BindingError::Invalid(format!(
+                "Unknown layout format {}; expected json or ron",
+                other
+            ))),
+        }
     }
-    SCM_UNSPECIFIED
 }
 
-unsafe extern "C" fn get_layout(state: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let scm = wm.layout.serialize(Serializer::default()).unwrap();
-    scm
+/// `root`'s tree as JSON, or RON with `format` `'ron`, in the form of
+/// `fwm::ExportedRoot`: each item's id, bounds and weight, each container's
+/// strategy, each window's client and title, and the point and cursor if
+/// they're in this root.
+#[scheme_fn("fwm-export-layout")]
+unsafe fn export_layout(
+    wm: &mut WmState,
+    root: usize,
+    #[optional] format: Option<String>,
+) -> Result<String, BindingError> {
+    let format = ExportFormat::parse(format)?;
+    wm.layout.check_root(root)?;
+    let display = wm.display;
+    let mut exported = wm.layout.export(root, |_, data| ExportedWindow {
+        client: data.client.as_ref().map(|client| client.window),
+        title: data
+            .client
+            .as_ref()
+            .and_then(|client| window_title(display, client.window)),
+    });
+    let in_root = |item| wm.layout.exists(item) && wm.layout.root_of(item) == Some(root);
+    exported.point = Some(wm.point).filter(|&point| in_root(point));
+    exported.cursor = wm.cursor.filter(|cursor| in_root(cursor.item()));
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&exported).map_err(|e| e.to_string()),
+        ExportFormat::Ron => {
+            ron::ser::to_string_pretty(&exported, Default::default()).map_err(|e| e.to_string())
+        }
+    }
+    .map_err(BindingError::Invalid)
+}
+
+/// Build a new root from `text`, as written by `fwm-export-layout` in
+/// `format`, and return it. Windows get new slots, and their clients are
+/// moved into them if they're still around. The point and cursor are ignored.
+#[scheme_fn("fwm-import-layout")]
+unsafe fn import_layout(
+    wm: &mut WmState,
+    text: String,
+    #[optional] format: Option<String>,
+) -> Result<usize, BindingError> {
+    let exported: ExportedRoot<ExportedWindow> = match ExportFormat::parse(format)? {
+        ExportFormat::Json => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        ExportFormat::Ron => ron::from_str(&text).map_err(|e| e.to_string()),
+    }
+    .map_err(BindingError::Invalid)?;
+    Ok(wm.import_layout(&exported)?)
+}
+
+/// With `hash_tables` true, the layout's maps, like its windows and containers,
+/// are returned as hash tables rather than alists.
+#[scheme_fn("fwm-get-layout")]
+fn get_layout(
+    wm: &mut WmState,
+    #[optional] hash_tables: Option<bool>,
+) -> Result<SCM, BindingError> {
+    let ser = serializer().maps_as_hash_tables(hash_tables.unwrap_or(false));
+    Ok(wm.layout.serialize(ser)?)
 }
 
-unsafe extern "C" fn set_focus(state: SCM, point: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let maybe_window: Option<usize> =
-        Deserialize::deserialize(Deserializer { scm: point }).expect("XXX");
+#[scheme_fn("fwm-set-focus")]
+unsafe fn set_focus(wm: &mut WmState, maybe_window: Option<usize>) -> Result<(), BindingError> {
     if let Some(window) = maybe_window {
-        assert!(wm.layout.exists(ItemIdx::Window(window))); // XXX
+        wm.layout.check_item(ItemIdx::Window(window))?;
     }
     wm.focused = maybe_window;
     wm.ensure_focus();
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn nth_child(state: SCM, container: SCM, index: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let container = usize::deserialize(Deserializer { scm: container }).expect("XXX");
-    let index = usize::deserialize(Deserializer { scm: index }).expect("XXX");
-    let cl = ChildLocation { container, index };
-    println!("{cl:?}");
-    let item = wm.layout.item_from_child_location(cl);
-    let scm = item.serialize(Serializer::default()).unwrap();
-    scm
+#[scheme_fn("fwm-nth-child")]
+fn nth_child(
+    wm: &mut WmState,
+    container: usize,
+    index: usize,
+) -> Result<Option<ItemIdx>, BindingError> {
+    Ok(wm
+        .layout
+        .checked_item_from_child_location(ChildLocation { container, index })?)
 }
 
-unsafe extern "C" fn child_location(state: SCM, point: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
+#[scheme_fn("fwm-child-location")]
+fn child_location(wm: &mut WmState, point: ItemIdx) -> Result<ChildLocation, BindingError> {
+    wm.layout.check_item(point)?;
     // XXX this is wrong
     let displayed_root = wm.displayed_root.unwrap_or(0);
-    let loc = wm.layout.child_location(point).unwrap_or(ChildLocation {
+    Ok(wm.layout.child_location(point).unwrap_or(ChildLocation {
         container: displayed_root,
         index: 0,
-    });
-    let scm = loc.serialize(Serializer::default()).unwrap();
-    scm
+    }))
 }
 
-unsafe extern "C" fn move_point_to_cursor(state: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
+#[scheme_fn("fwm-move-point-to-cursor")]
+fn move_point_to_cursor(wm: &mut WmState) -> Result<(), BindingError> {
     if let Some(cursor) = wm.cursor {
-        if !wm.layout.is_ancestor(wm.point, cursor.item()) {
-            wm.do_and_recompute(|wm| {
-                let actions = wm.layout.r#move(wm.point, cursor);
-                actions
-            });
-        }
+        wm.try_do_and_recompute(|wm| wm.layout.checked_move(wm.point, cursor))?;
+    }
+    Ok(())
+}
+
+#[scheme_fn("fwm-all-descendants")]
+fn all_descendants(wm: &mut WmState, point: ItemIdx) -> Result<Vec<ItemIdx>, BindingError> {
+    wm.layout.check_item(point)?;
+    Ok(wm.layout.iter_descendants(point).collect())
+}
+
+/// A procedure for the main thread to apply to the WM state.
+struct MainThreadCall {
+    f: Rooted,
+    /// Where `fwm-mt-sync` waits for the result, or a description of the exception.
+    reply: Option<mpsc::Sender<Result<Rooted, String>>>,
+}
+
+fn send_to_main_thread(call: MainThreadCall) -> Result<(), BindingError> {
+    let mut tx = FEEDBACK_TX
+        .get()
+        .ok_or_else(|| BindingError::Invalid("The WM is not running".to_string()))?;
+    tx.write_u64::<NativeEndian>(Box::into_raw(Box::new(call)) as u64)
+        .unwrap();
+    Ok(())
+}
+
+/// Whether this is the thread running the WM, which mustn't wait for itself.
+fn on_main_thread() -> bool {
+    MAIN_THREAD.get() == Some(&std::thread::current().id())
+}
+
+#[scheme_fn("fwm-mt")]
+fn do_on_main_thread(f: SCM) -> Result<(), BindingError> {
+    send_to_main_thread(MainThreadCall {
+        f: Rooted::new(f),
+        reply: None,
+    })
+}
+
+/// Like `fwm-mt`, but waits for `f` to be applied, and returns its result.
+/// This is how other threads, like the REPL's, get at the WM state.
+#[scheme_fn("fwm-mt-sync")]
+fn do_on_main_thread_sync(f: SCM) -> Result<SCM, BindingError> {
+    if on_main_thread() {
+        return Err(BindingError::Invalid(
+            "fwm-mt-sync can't be called from the main thread".to_string(),
+        ));
+    }
+    let (reply, result) = mpsc::channel();
+    send_to_main_thread(MainThreadCall {
+        f: Rooted::new(f),
+        reply: Some(reply),
+    })?;
+    match result.recv() {
+        Ok(Ok(value)) => Ok(value.get()),
+        Ok(Err(exception)) => Err(BindingError::Invalid(exception)),
+        Err(_) => Err(BindingError::Invalid("The WM stopped".to_string())),
     }
-    SCM_UNSPECIFIED
 }
 
-unsafe extern "C" fn all_descendants(state: SCM, point: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let iter = wm.layout.iter_descendants(point);
-    let result_list = serde::Serializer::collect_seq(Serializer::default(), iter).unwrap();
-    result_list
+/// `value` as a string of JSON, for `fwmc --json`. Records become objects
+/// of their fields, with their type's name under `$type`.
+#[scheme_fn("fwm-to-json")]
+fn to_json(value: SCM) -> Result<String, BindingError> {
+    let value = serde_json::Value::deserialize(Deserializer::new(value))?;
+    Ok(value.to_string())
 }
 
-unsafe extern "C" fn do_on_main_thread(f: SCM) -> SCM {
-    let mut tx = FEEDBACK_TX.get().expect("wm not initialized!");
-    tx.write_u64::<NativeEndian>(f as u64).unwrap();
-    SCM_UNSPECIFIED
+/// Wait for the next event, which must be one of `kinds` if they're given,
+/// like `'(point-changed)`. Only events from during the call are seen; to
+/// follow every event, read the event socket instead.
+#[scheme_fn("fwm-wait-event")]
+fn wait_event(#[optional] kinds: Option<Vec<String>>) -> Result<Event, BindingError> {
+    if on_main_thread() {
+        return Err(BindingError::Invalid(
+            "fwm-wait-event can't be called from the main thread".to_string(),
+        ));
+    }
+    // Dropped on return, which unsubscribes.
+    let events = subscribe_events();
+    loop {
+        let event = events
+            .recv()
+            .map_err(|_| BindingError::Invalid("The WM stopped".to_string()))?;
+        if kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.iter().any(|kind| kind == event.kind()))
+        {
+            return Ok(event);
+        }
+    }
 }
 
-unsafe extern "C" fn set_length(state: SCM, point: SCM, length: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
-    let length = match usize::deserialize(Deserializer { scm: length }) {
+#[scheme_fn("fwm-set-length")]
+fn set_length(wm: &mut WmState, point: ItemIdx, length: SCM) -> Result<(), BindingError> {
+    let length = match usize::deserialize(Deserializer::new(length)) {
         Ok(length) => length,
         Err(_e) => {
-            isize::deserialize(Deserializer { scm: length }).unwrap();
+            isize::deserialize(Deserializer::new(length))?;
             0
         }
     };
     let length = if length == 0 { 1 } else { length };
-    wm.do_and_recompute(|wm| wm.layout.set_content_length(point, length));
-    SCM_UNSPECIFIED
+    wm.try_do_and_recompute(|wm| wm.layout.checked_set_content_length(point, length))?;
+    Ok(())
 }
 
-unsafe extern "C" fn get_length(state: SCM, point: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
+#[scheme_fn("fwm-get-length")]
+fn get_length(wm: &mut WmState, point: ItemIdx) -> Result<SCM, BindingError> {
+    wm.layout.check_item(point)?;
     match wm.layout.get_content_length(point) {
-        Some(length) => length.serialize(Serializer::default()).unwrap(),
-        None => SCM_BOOL_F,
+        Some(length) => Ok(length.serialize(serializer())?),
+        None => Ok(SCM_BOOL_F),
     }
 }
 
-unsafe extern "C" fn equalize_lengths(state: SCM, point: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let point = ItemIdx::deserialize(Deserializer { scm: point }).expect("XXX");
+#[scheme_fn("fwm-equalize-lengths")]
+fn equalize_lengths(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
+    wm.layout.check_item(point)?;
     if let ItemIdx::Container(c_idx) = point {
-        wm.do_and_recompute(|wm| wm.layout.equalize_container_children(c_idx))
+        wm.try_do_and_recompute(|wm| wm.layout.checked_equalize_container_children(c_idx))?;
     }
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-unsafe extern "C" fn show_root(state: SCM, root: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let root = Option::<usize>::deserialize(Deserializer { scm: root }).expect("XXX");
+#[scheme_fn("fwm-show-root")]
+unsafe fn show_root(wm: &mut WmState, root: Option<usize>) -> Result<(), BindingError> {
+    if let Some(root) = root {
+        wm.layout.check_root(root)?;
+    }
 
     if wm.displayed_root != root {
         wm.unmap_all();
@@ -2066,105 +3408,152 @@ unsafe extern "C" fn show_root(state: SCM, root: SCM) -> SCM {
         wm.displayed_root = root;
         wm.do_resize();
         wm.map_all();
+        publish_event(Event::RootShown { root });
+    }
+
+    Ok(())
+}
+
+#[scheme_fn("fwm-alloc-root")]
+fn alloc_root(wm: &mut WmState) -> Result<usize, BindingError> {
+    Ok(wm.layout.checked_alloc_root()?)
+}
+
+/// One entry of the list returned by `fwm-list-roots`.
+#[derive(Serialize, Deserialize)]
+struct RootListing<'a> {
+    root: usize,
+    name: Option<&'a str>,
+    last_focused: Option<ItemIdx>,
+    displayed: bool,
+}
+
+/// The root whose history `fwm-undo` and `fwm-redo` act on.
+fn history_root(wm: &WmState) -> Option<usize> {
+    wm.layout.root_of(wm.point).or(wm.displayed_root)
+}
+
+#[scheme_fn("fwm-undo")]
+unsafe fn undo(wm: &mut WmState) -> Result<(), BindingError> {
+    if let Some(root) = history_root(wm) {
+        wm.step_history(root, false);
+    }
+    Ok(())
+}
+
+#[scheme_fn("fwm-redo")]
+unsafe fn redo(wm: &mut WmState) -> Result<(), BindingError> {
+    if let Some(root) = history_root(wm) {
+        wm.step_history(root, true);
+    }
+    Ok(())
+}
+
+#[scheme_fn("fwm-list-roots")]
+fn list_roots(wm: &mut WmState) -> Result<Vec<RootListing<'_>>, BindingError> {
+    let roots = wm.layout.roots().map(|root| RootListing {
+        root,
+        name: wm.layout.root_name(root),
+        last_focused: wm.layout.last_focused(root),
+        displayed: wm.displayed_root == Some(root),
+    });
+    Ok(roots.collect())
+}
+
+#[scheme_fn("fwm-destroy-root")]
+unsafe fn destroy_root(
+    wm: &mut WmState,
+    root: usize,
+    rehome: Option<usize>,
+) -> Result<(), BindingError> {
+    if [wm.displayed_root, wm.scratch_root, wm.float_root].contains(&Some(root)) {
+        return Err(BindingError::Invalid(format!(
+            "Can't destroy root {}: it's in use",
+            root
+        )));
     }
+    Ok(wm.destroy_root(root, rehome)?)
+}
+
+#[scheme_fn("fwm-root-name")]
+fn root_name(wm: &mut WmState, root: usize) -> Result<Option<&str>, BindingError> {
+    wm.layout.check_root(root)?;
+    Ok(wm.layout.root_name(root))
+}
+
+#[scheme_fn("fwm-set-root-name")]
+fn set_root_name(wm: &mut WmState, root: usize, name: Option<String>) -> Result<(), BindingError> {
+    wm.layout.check_root(root)?;
+    wm.layout.set_root_name(root, name);
+    Ok(())
+}
+
+#[scheme_fn("fwm-root-last-focused")]
+fn root_last_focused(wm: &mut WmState, root: usize) -> Result<Option<ItemIdx>, BindingError> {
+    wm.layout.check_root(root)?;
+    Ok(wm.layout.last_focused(root))
+}
+
+#[scheme_fn("fwm-send-to-root")]
+unsafe fn send_to_root(wm: &mut WmState, point: ItemIdx, root: usize) -> Result<(), BindingError> {
+    Ok(wm.send_to_root(point, root)?)
+}
+
+#[scheme_fn("fwm-send-to-scratch")]
+unsafe fn send_to_scratch(wm: &mut WmState, point: ItemIdx) -> Result<(), BindingError> {
+    Ok(wm.send_to_scratch(point)?)
+}
 
-    SCM_UNSPECIFIED
+#[scheme_fn("fwm-scratch-toggle")]
+unsafe fn scratch_toggle(
+    wm: &mut WmState,
+    point: ItemIdx,
+    how: Summon,
+) -> Result<(), BindingError> {
+    wm.layout.check_non_root(point)?;
+    if wm.layout.root_of(point) == wm.scratch_root {
+        wm.summon(point, how)?;
+    } else {
+        wm.send_to_scratch(point)?;
+    }
+    Ok(())
 }
 
-unsafe extern "C" fn alloc_root(state: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let root = wm.layout.alloc_root();
-    root.serialize(Serializer::default()).unwrap()
+#[scheme_fn("fwm-scratch-items")]
+fn scratch_items(wm: &mut WmState) -> Result<Vec<ItemIdx>, BindingError> {
+    let scratch_root = wm.scratch_root();
+    let items = wm
+        .layout
+        .checked_children(scratch_root)?
+        .iter()
+        .map(|&(_weight, child)| child);
+    Ok(items.collect())
 }
 
-unsafe extern "C" fn _debug_force_resize(state: SCM, width: SCM, height: SCM) -> SCM {
-    let wm = get_foreign_object::<WmState>(state, WM_STATE_TYPE);
-    let width = usize::deserialize(Deserializer { scm: width }).expect("XXX");
-    let height = usize::deserialize(Deserializer { scm: height }).expect("XXX");
+#[scheme_fn("fwm-DEBUG-force-resize")]
+fn _debug_force_resize(wm: &mut WmState, width: usize, height: usize) -> Result<(), BindingError> {
     wm.root_size = AreaSize { width, height };
     wm.do_resize();
-    SCM_UNSPECIFIED
+    Ok(())
 }
 
-// TODO - codegen this, as well as translating Scheme objects to Rust objects in the function bodies
-// (similar to what we did in PyTorch)
 unsafe extern "C" fn scheme_setup(_data: *mut c_void) -> *mut c_void {
-    let kc_name = scm_from_utf8_symbol(CStr::from_bytes_with_nul(b"key-combo\0").unwrap().as_ptr());
-    let kc_slots = scm_list_1(scm_from_utf8_symbol(
-        CStr::from_bytes_with_nul(b"data\0").unwrap().as_ptr(),
-    ));
-    KEY_COMBO_TYPE = scm_make_foreign_object_type(kc_name, kc_slots, None);
-
-    let wm_name = scm_from_utf8_symbol(CStr::from_bytes_with_nul(b"wm-state\0").unwrap().as_ptr());
-    let wm_slots = scm_list_1(scm_from_utf8_symbol(
-        CStr::from_bytes_with_nul(b"data\0").unwrap().as_ptr(),
-    ));
-    WM_STATE_TYPE = scm_make_foreign_object_type(wm_name, wm_slots, None);
-
-    let c = CStr::from_bytes_with_nul(b"fwm-run-wm\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, run_wm as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-parse-key-combo\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, parse_key_combo as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-write-key-combo\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, write_key_combo as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-navigate\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, navigate as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-cursor\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, cursor as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-get-point\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, get_point as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-set-point\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, set_point as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-occupied?\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, is_occupied as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-nearest-container\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, nearest_container as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-n-children\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, n_children as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-make-cursor-into\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, make_cursor_into as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-make-cursor-before\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, make_cursor_before as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-kill-item-at\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, kill_item_at as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-kill-client-at\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, kill_client_at as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-get-layout\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, get_layout as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-set-focus\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, set_focus as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-clear-bindings\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, clear_bindings as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-set-cursor\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, set_cursor as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-get-cursor\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, get_cursor as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-nth-child\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 3, 0, 0, nth_child as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-child-location\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, child_location as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-move-point-to-cursor\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, move_point_to_cursor as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-new-window-at\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, new_window_at as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-all-descendants\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, all_descendants as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-request-kill-client-at\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, request_kill_client_at as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-mt\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, do_on_main_thread as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-set-length\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 3, 0, 0, set_length as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-get-length\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, get_length as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-equalize-lengths\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, equalize_lengths as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-show-root\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 2, 0, 0, show_root as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-alloc-root\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 1, 0, 0, alloc_root as *mut c_void);
-    let c = CStr::from_bytes_with_nul(b"fwm-DEBUG-force-resize\0").unwrap();
-    scm_c_define_gsubr(c.as_ptr(), 3, 0, 0, _debug_force_resize as *mut c_void);
+    for f in btv_scheme::scheme_fns() {
+        f.define();
+    }
+
+    // Accessors and predicates for the records the bindings return,
+    // like `child-location-container` and `move-cursor-into?`.
+    define_records::<ItemIdx>().unwrap();
+    define_records::<MoveCursor>().unwrap();
+    define_records::<MoveOrReplace>().unwrap();
+    define_records::<ChildLocation>().unwrap();
+    define_records::<RootListing>().unwrap();
+    define_records::<Theme>().unwrap();
+    define_records::<Gaps>().unwrap();
+    define_records::<Padding>().unwrap();
+    // The constructor isn't serialized, so any type does for it.
+    define_records::<Layout<WindowData, ContainerData, ()>>().unwrap();
     std::ptr::null_mut()
 }
 
@@ -2178,12 +3567,19 @@ use clap::Parser;
 struct Args {
     #[clap(long)]
     init: String,
+    /// Reconfigure whenever the init file changes.
+    #[clap(long)]
+    watch_init: bool,
 }
 
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let args = Args::parse();
     let mut init = args.init;
+    let _ = INIT_FILE.set(InitFile {
+        path: init.clone(),
+        watch: args.watch_init,
+    });
     init.push(0 as char);
     unsafe {
         let old_umask = umask(0);
@@ -2194,6 +3590,23 @@ fn main() {
         let mut socket_path = std::env::temp_dir();
         socket_path.push(format!("fwm.{}", std::process::id()));
         info!("Socket path: {}", socket_path.display());
+        // For `fwmc`, run from the clients.
+        std::env::set_var("FWM_SOCKET", &socket_path);
+        let mut events_path = std::env::temp_dir();
+        events_path.push(format!("fwm-events.{}", std::process::id()));
+        // A crashed fwm with our pid could have left its socket behind.
+        let _ = std::fs::remove_file(&events_path);
+        match UnixListener::bind(&events_path) {
+            Ok(listener) => {
+                std::thread::spawn(move || serve_events(listener));
+                info!("Event socket path: {}", events_path.display());
+                std::env::set_var("FWM_EVENTS_SOCKET", &events_path);
+            }
+            Err(e) => error!(
+                "Failed to bind the event socket at {}, running without it: {e}",
+                events_path.display()
+            ),
+        }
         let listen_arg = format!(
             "--listen={}\0",
             socket_path
@@ -1,4 +1,9 @@
+use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -54,6 +59,15 @@ pub enum ItemIdx {
     Container(usize),
 }
 
+impl std::fmt::Display for ItemIdx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ItemIdx::Window(w_idx) => write!(f, "window {}", w_idx),
+            ItemIdx::Container(c_idx) => write!(f, "container {}", c_idx),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ItemAndData<W, C> {
     Window(usize, W),
@@ -82,11 +96,62 @@ pub struct Container<C> {
     children: Vec<(f64, ItemIdx)>,
     parent: Option<usize>, // None for root
     bounds: WindowBounds,
-    inter: usize,
-    padding: usize,
+    /// Overrides the layout's default gaps.
+    gaps: Option<Gaps>,
+    /// Overrides the layout's default padding.
+    padding: Option<Padding>,
     data: C,
 }
 
+/// Space between a container's edges and its children, on each side.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Padding {
+    pub left: usize,
+    pub right: usize,
+    pub top: usize,
+    pub bottom: usize,
+}
+
+impl Padding {
+    pub const fn uniform(n: usize) -> Self {
+        Self {
+            left: n,
+            right: n,
+            top: n,
+            bottom: n,
+        }
+    }
+    /// The padding along `strategy`'s axis: before the first child and after the last.
+    fn main(&self, strategy: LayoutStrategy) -> (usize, usize) {
+        match strategy {
+            LayoutStrategy::Horizontal => (self.left, self.right),
+            LayoutStrategy::Vertical => (self.top, self.bottom),
+        }
+    }
+}
+
+impl std::ops::Add for Padding {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            left: self.left + other.left,
+            right: self.right + other.right,
+            top: self.top + other.top,
+            bottom: self.bottom + other.bottom,
+        }
+    }
+}
+
+/// Space left empty around items, in addition to containers' padding.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Gaps {
+    /// Between a container's children.
+    pub inner: usize,
+    /// Between a root's edges and its children.
+    pub outer: usize,
+}
+
 pub enum LayoutData<W, C> {
     Window(W),
     Container(C),
@@ -176,12 +241,483 @@ impl IdGen for usize {
     }
 }
 
+/// Bookkeeping attached to each root.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RootInfo {
+    pub name: Option<String>,
+    /// The item that most recently had the point in this root.
+    /// May be stale; see `Layout::last_focused`.
+    pub last_focused: Option<ItemIdx>,
+    /// Space left empty at the root's edges, like for a bar.
+    pub margins: Padding,
+}
+
+/// The shape of a root's tree: which items it contains, their weights,
+/// and the containers' strategies. Bounds and item data aren't recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    root: usize,
+    tree: SnapshotNode,
+}
+
+impl Snapshot {
+    pub fn root(&self) -> usize {
+        self.root
+    }
+    /// Every item in the snapshot, including the root.
+    fn items(&self) -> Vec<ItemIdx> {
+        let mut items = vec![];
+        self.tree.collect_items(&mut items);
+        items
+    }
+}
+
+impl SnapshotNode {
+    fn collect_items(&self, items: &mut Vec<ItemIdx>) {
+        match self {
+            SnapshotNode::Window(w_idx) => items.push(ItemIdx::Window(*w_idx)),
+            SnapshotNode::Container {
+                c_idx, children, ..
+            } => {
+                items.push(ItemIdx::Container(*c_idx));
+                for (_weight, child) in children {
+                    child.collect_items(items);
+                }
+            }
+        }
+    }
+}
+
+/// Undo and redo stacks of each root's shape.
+///
+/// Rather than snapshotting every root around each change, `History` keeps
+/// the shape each root had when last recorded, and is told which items
+/// changed. Only the roots those items are in, or were in, are compared.
+#[derive(Debug)]
+pub struct History {
+    /// How many changes are remembered per root.
+    limit: usize,
+    roots: BTreeMap<usize, RootHistory>,
+    /// The root each item was in, as of that root's recorded shape.
+    owners: HashMap<ItemIdx, usize>,
+    /// The root `step` is restoring, whose next change is the restore itself.
+    restoring: Option<usize>,
+}
+
+#[derive(Debug)]
+struct RootHistory {
+    shape: Snapshot,
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            roots: Default::default(),
+            owners: Default::default(),
+            restoring: None,
+        }
+    }
+
+    /// Start tracking the roots of `layout` that aren't tracked yet, in their current shape,
+    /// and forget those that are gone. Call this before changing them, so their old shape is known.
+    pub fn track_roots<W, C, CCtor>(&mut self, layout: &Layout<W, C, CCtor>)
+    where
+        W: Serialize + for<'d> Deserialize<'d>,
+        C: Serialize + for<'d> Deserialize<'d>,
+        CCtor: Constructor<Item = C>,
+    {
+        let gone = self
+            .roots
+            .keys()
+            .copied()
+            .filter(|&root| !layout.roots.contains_key(&root))
+            .collect::<Vec<_>>();
+        for root in gone {
+            self.forget(root);
+        }
+        for root in layout.roots() {
+            if !self.roots.contains_key(&root) {
+                let shape = layout.snapshot(root);
+                self.claim(&shape);
+                self.roots.insert(
+                    root,
+                    RootHistory {
+                        shape,
+                        undo: VecDeque::new(),
+                        redo: vec![],
+                    },
+                );
+            }
+        }
+    }
+
+    /// Record the changes to the roots that the `touched` items are in, or were in.
+    /// Each root whose shape changed has its old shape pushed onto its undo stack,
+    /// and its redo stack cleared.
+    pub fn record<W, C, CCtor>(
+        &mut self,
+        layout: &Layout<W, C, CCtor>,
+        touched: impl IntoIterator<Item = ItemIdx>,
+    ) where
+        W: Serialize + for<'d> Deserialize<'d>,
+        C: Serialize + for<'d> Deserialize<'d>,
+        CCtor: Constructor<Item = C>,
+    {
+        let mut roots = BTreeSet::new();
+        roots.extend(self.restoring);
+        for item in touched {
+            roots.extend(self.owners.get(&item).copied());
+            if layout.exists(item) {
+                roots.extend(layout.root_of(item));
+            }
+        }
+        let restoring = self.restoring.take();
+        for root in roots {
+            if !layout.roots.contains_key(&root) {
+                self.forget(root);
+                continue;
+            }
+            let shape = layout.snapshot(root);
+            let Some(history) = self.roots.get_mut(&root) else {
+                // Made since `track_roots`; there's nothing to undo.
+                self.track_roots(layout);
+                continue;
+            };
+            if history.shape == shape {
+                continue;
+            }
+            let old = std::mem::replace(&mut history.shape, shape.clone());
+            if restoring != Some(root) {
+                history.undo.push_back(old.clone());
+                if history.undo.len() > self.limit {
+                    history.undo.pop_front();
+                }
+                history.redo.clear();
+            }
+            self.disown(&old);
+            self.claim(&shape);
+        }
+    }
+
+    /// The shape to restore `root` to, to undo its last change (or redo its last undone change).
+    /// Its recorded shape is moved onto the other stack, and the restore itself,
+    /// once recorded, counts as neither a new change nor an undone one.
+    pub fn step(&mut self, root: usize, redo: bool) -> Option<Snapshot> {
+        let history = self.roots.get_mut(&root)?;
+        let snapshot = if redo {
+            history.redo.pop()?
+        } else {
+            history.undo.pop_back()?
+        };
+        if redo {
+            history.undo.push_back(history.shape.clone());
+            if history.undo.len() > self.limit {
+                history.undo.pop_front();
+            }
+        } else {
+            history.redo.push(history.shape.clone());
+        }
+        self.restoring = Some(root);
+        Some(snapshot)
+    }
+
+    /// How many changes to `root` can be undone and redone.
+    pub fn depth(&self, root: usize) -> (usize, usize) {
+        self.roots
+            .get(&root)
+            .map_or((0, 0), |history| (history.undo.len(), history.redo.len()))
+    }
+
+    fn forget(&mut self, root: usize) {
+        if let Some(history) = self.roots.remove(&root) {
+            self.disown(&history.shape);
+        }
+    }
+
+    fn claim(&mut self, shape: &Snapshot) {
+        for item in shape.items() {
+            self.owners.insert(item, shape.root);
+        }
+    }
+
+    fn disown(&mut self, shape: &Snapshot) {
+        for item in shape.items() {
+            if self.owners.get(&item) == Some(&shape.root) {
+                self.owners.remove(&item);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum SnapshotNode {
+    Window(usize),
+    Container {
+        c_idx: usize,
+        strategy: LayoutStrategy,
+        children: Vec<(f64, SnapshotNode)>,
+    },
+}
+
+/// The version of the `ExportedRoot` format, bumped whenever it changes
+/// incompatibly.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A root's tree in a stable form for other programs to read, render or
+/// edit, written by `Layout::export` and read back by `Layout::import`.
+/// Unlike `Snapshot`, this has everyone's bounds, and whatever the caller
+/// records about each window in `E`.
+///
+/// `point` and `cursor` are filled in by the caller, since the layout
+/// doesn't know them, and are `None` unless they're in this root.
+///
+/// In JSON, as `fwm-export-layout` writes it, enums are externally tagged
+/// (`{"Window": 123}`, `"Horizontal"`), `None` is `null`, and each child is a
+/// `[weight, node]` pair. Two windows side by side, with fwm's `E`, which
+/// records each window's client and title:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "root": 42,
+///   "name": "1",
+///   "point": {"Window": 123},
+///   "cursor": null,
+///   "tree": {"Container": {
+///     "id": 42,
+///     "strategy": "Horizontal",
+///     "bounds": {"content": {"height": 50, "width": 100},
+///                "position": {"x": 0, "y": 0, "root_ctr": 42}},
+///     "children": [
+///       [1.0, {"Window": {
+///         "id": 123,
+///         "bounds": {"content": {"height": 50, "width": 50},
+///                    "position": {"x": 0, "y": 0, "root_ctr": 42}},
+///         "data": {"client": 4194313, "title": "xterm"}}}],
+///       [1.0, {"Window": {
+///         "id": 124,
+///         "bounds": {"content": {"height": 50, "width": 50},
+///                    "position": {"x": 50, "y": 0, "root_ctr": 42}},
+///         "data": {"client": null, "title": null}}}]
+///     ]}}
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRoot<E> {
+    /// `EXPORT_VERSION` when this was written.
+    pub version: u32,
+    pub root: usize,
+    pub name: Option<String>,
+    pub point: Option<ItemIdx>,
+    pub cursor: Option<MoveCursor>,
+    /// The root container itself.
+    pub tree: ExportedNode<E>,
+}
+
+/// An item of an `ExportedRoot`. `id`s are the items' indices in the layout,
+/// which `ItemIdx`s elsewhere in the export refer to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExportedNode<E> {
+    Window {
+        id: usize,
+        bounds: WindowBounds,
+        data: E,
+    },
+    Container {
+        id: usize,
+        strategy: LayoutStrategy,
+        bounds: WindowBounds,
+        /// In order, with their weights, which are relative to each other.
+        children: Vec<(f64, ExportedNode<E>)>,
+    },
+}
+
+/// The result of `Layout::import`.
+#[derive(Debug)]
+pub struct Imported<W, C> {
+    /// The new root.
+    pub root: usize,
+    /// The indices of the new windows, by their exported ids.
+    pub windows: BTreeMap<usize, usize>,
+    pub actions: Vec<LayoutAction<W, C>>,
+}
+
+/// Bad input to one of the `checked_*` methods of `Layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutError {
+    NoSuchItem(ItemIdx),
+    /// The container exists, but has a parent.
+    NotARoot(usize),
+    /// The operation doesn't apply to roots.
+    IsARoot(usize),
+    /// The cursor's container or item doesn't exist, or its index is out of range.
+    InvalidCursor(MoveCursor),
+    /// `item` can't be moved somewhere inside itself.
+    MoveIntoSelf {
+        item: ItemIdx,
+        cursor: MoveCursor,
+    },
+    NoSuchChild(ChildLocation),
+    /// There's no constructor for container data, e.g. because the layout was deserialized.
+    NoConstructor,
+    /// An `ExportedRoot` was written by an incompatible version.
+    UnsupportedExportVersion(u32),
+    /// The top of an `ExportedRoot`'s tree isn't a container, the children of
+    /// the container with this exported id have weights that are negative,
+    /// non-finite, or all zero, or more than one window has this exported id.
+    BadExport(ItemIdx),
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::NoSuchItem(item) => write!(f, "{} doesn't exist", item),
+            LayoutError::NotARoot(c_idx) => write!(f, "container {} isn't a root", c_idx),
+            LayoutError::IsARoot(c_idx) => {
+                write!(
+                    f,
+                    "container {} is a root, which this doesn't apply to",
+                    c_idx
+                )
+            }
+            LayoutError::InvalidCursor(cursor) => write!(f, "cursor {:?} is out of date", cursor),
+            LayoutError::MoveIntoSelf { item, cursor } => {
+                write!(f, "can't move {} inside itself, to {:?}", item, cursor)
+            }
+            LayoutError::NoSuchChild(ChildLocation { container, index }) => {
+                write!(f, "container {} has no child at index {}", container, index)
+            }
+            LayoutError::NoConstructor => {
+                write!(f, "the layout has no constructor for container data")
+            }
+            LayoutError::UnsupportedExportVersion(version) => write!(
+                f,
+                "exported layout has version {}, but only version {} is supported",
+                version, EXPORT_VERSION
+            ),
+            LayoutError::BadExport(item) => write!(f, "exported {} is malformed", item),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// A broken structural invariant of `Layout`, as found by `Layout::check_invariants`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantViolation {
+    /// `container` lists `child`, which doesn't exist.
+    MissingChild { container: usize, child: ItemIdx },
+    /// `item` is listed by `container`, but its parent pointer says otherwise.
+    WrongParent {
+        item: ItemIdx,
+        container: usize,
+        parent: Option<usize>,
+    },
+    /// `item` claims `parent` as its parent, but isn't among its children.
+    NotAmongChildren { item: ItemIdx, parent: usize },
+    /// `item` is listed as a child by more than one container, or more than once by one.
+    DuplicateChild { item: ItemIdx },
+    /// `root` is registered as a root, but doesn't exist or has a parent.
+    BadRoot { root: usize },
+    /// `container` has no parent, but isn't registered as a root.
+    UnregisteredRoot { container: usize },
+    /// Non-root `container` has fewer than two children, so it should have been fused away.
+    UnderfullContainer { container: usize, n_children: usize },
+    /// `item`'s bounds name a different root than the one it's actually under.
+    WrongRootCtr {
+        item: ItemIdx,
+        actual: usize,
+        recorded: usize,
+    },
+    /// Following parent pointers from `item` never reaches a root.
+    ParentCycle { item: ItemIdx },
+    /// `container`'s children have a negative or non-finite weight, or a non-positive total.
+    BadWeights { container: usize, weights: Vec<f64> },
+}
+
+impl std::fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantViolation::MissingChild { container, child } => write!(
+                f,
+                "container {} lists {} as a child, but it doesn't exist",
+                container, child
+            ),
+            InvariantViolation::WrongParent {
+                item,
+                container,
+                parent: Some(parent),
+            } => write!(
+                f,
+                "{} is a child of container {}, but its parent is container {}",
+                item, container, parent
+            ),
+            InvariantViolation::WrongParent {
+                item,
+                container,
+                parent: None,
+            } => write!(
+                f,
+                "{} is a child of container {}, but has no parent",
+                item, container
+            ),
+            InvariantViolation::NotAmongChildren { item, parent } => write!(
+                f,
+                "{} has container {} as its parent, but isn't among its children",
+                item, parent
+            ),
+            InvariantViolation::DuplicateChild { item } => {
+                write!(f, "{} is listed as a child more than once", item)
+            }
+            InvariantViolation::BadRoot { root } => {
+                write!(f, "root {} doesn't exist or has a parent", root)
+            }
+            InvariantViolation::UnregisteredRoot { container } => {
+                write!(f, "container {} has no parent, but isn't a root", container)
+            }
+            InvariantViolation::UnderfullContainer {
+                container,
+                n_children,
+            } => write!(
+                f,
+                "container {} isn't a root, but has only {} children",
+                container, n_children
+            ),
+            InvariantViolation::WrongRootCtr {
+                item,
+                actual,
+                recorded,
+            } => write!(
+                f,
+                "{} is under root {}, but its bounds say root {}",
+                item, actual, recorded
+            ),
+            InvariantViolation::ParentCycle { item } => {
+                write!(f, "the parents of {} never reach a root", item)
+            }
+            InvariantViolation::BadWeights { container, weights } => write!(
+                f,
+                "container {} has bad child weights {:?}",
+                container, weights
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Layout<W, C, CCtor> {
     windows: BTreeMap<usize, Window<W>>,
     containers: BTreeMap<usize, Container<C>>,
-    // roots: BTreeMap<usize, WindowBounds>,
-    default_padding: usize,
+    roots: BTreeMap<usize, RootInfo>,
+    default_padding: Padding,
+    default_gaps: Gaps,
+    /// Whether a container with only one child has no gaps.
+    smart_gaps: bool,
     #[serde(skip)]
     cctor: Option<CCtor>,
     window_idgen: usize,
@@ -447,9 +983,9 @@ where
                         },
                         parent: Some(parent),
                         bounds,
-                        inter: 0, // TODO - this should be configurable.
+                        gaps: None,
                         data: self.cctor.as_mut().expect("Must set cctor!").construct(),
-                        padding: self.default_padding,
+                        padding: None,
                     },
                 );
                 let ctr = self.containers.get_mut(&parent).unwrap();
@@ -485,16 +1021,21 @@ where
                             self.set_parent_unchecked(child, Some(next_c_idx))
                         }
 
-                        let Container {
-                            strategy,
-                            children,
-                            bounds,
-                            inter,
-                            ..
-                        } = self.containers.get_mut(&root_ctr_idx).unwrap();
+                        let data = self.cctor.as_mut().expect("Must set cctor!").construct();
+                        let root = self.containers.get_mut(&root_ctr_idx).unwrap();
+                        // The root keeps its identity and data; its old children
+                        // move into a new container beside `inserted`.
+                        let old_children = std::mem::replace(
+                            &mut root.children,
+                            if inserted_first {
+                                vec![(1.0, inserted), (1.0, ItemIdx::Container(next_c_idx))]
+                            } else {
+                                vec![(1.0, ItemIdx::Container(next_c_idx)), (1.0, inserted)]
+                            },
+                        );
                         let new_ctr = Container {
-                            strategy: *strategy,
-                            children: std::mem::take(children),
+                            strategy: root.strategy,
+                            children: old_children,
                             bounds: WindowBounds {
                                 content: Default::default(),
                                 position: Position {
@@ -503,29 +1044,13 @@ where
                                     root_ctr: root_ctr_idx,
                                 },
                             },
-                            inter: *inter,
+                            gaps: root.gaps,
                             parent: Some(root_ctr_idx),
-                            data: self.cctor.as_mut().expect("Must set cctor!").construct(),
-                            padding: self.default_padding,
+                            data,
+                            padding: None,
                         };
-                        let bounds = *bounds;
+                        root.strategy = strategy;
                         self.containers.insert(next_c_idx, new_ctr);
-                        self.containers.insert(
-                            root_ctr_idx,
-                            Container {
-                                strategy: LayoutStrategy::Horizontal,
-                                children: if inserted_first {
-                                    vec![(1.0, inserted), (1.0, ItemIdx::Container(next_c_idx))]
-                                } else {
-                                    vec![(1.0, ItemIdx::Container(next_c_idx)), (1.0, inserted)]
-                                },
-                                parent: None,
-                                bounds,
-                                inter: 0,
-                                data: self.cctor.as_mut().expect("Must set cctor!").construct(),
-                                padding: self.default_padding,
-                            },
-                        );
                         self.set_parent_unchecked(inserted, Some(root_ctr_idx));
                         root_ctr_idx
                     }
@@ -552,6 +1077,10 @@ where
         info!("Setting length of {:?} to {}", item, new_length);
         if let Some(SlotInContainer { c_idx, .. }) = self.slot_in_container(item) {
             let available_length = self.ctr_available_length(c_idx);
+            if available_length == 0 || self.containers[&c_idx].children.len() < 2 {
+                // Nothing to share out, or nobody to share it with.
+                return out;
+            }
             let new_length = new_length.min(available_length - 1);
             let remaining_length = available_length - new_length;
             let children = &mut self.containers.get_mut(&c_idx).unwrap().children;
@@ -567,15 +1096,20 @@ where
                     },
                 )
                 .sum();
+            let n_others = (children.len() - 1) as f64;
             for (weight, child) in children {
                 if *child == item {
                     *weight = new_length as f64;
-                } else {
+                } else if total_weight_of_others > 0.0 {
                     *weight = (*weight / total_weight_of_others) * (remaining_length as f64);
+                } else {
+                    // The others had all been squeezed to nothing; share equally.
+                    *weight = remaining_length as f64 / n_others;
                 }
             }
             self.layout(ItemIdx::Container(c_idx), &mut out);
         }
+        self.debug_check_invariants();
         out
     }
     pub fn ctr_available_length(&self, c_idx: usize) -> usize {
@@ -590,21 +1124,28 @@ where
         let ctr = &self.containers[&c_idx];
         let strat = ctr.strategy;
         let ctr_bounds = ctr.bounds;
-        let total_inter = ctr.inter * (ctr.children.len().saturating_sub(1));
+        let total_inter = self.gaps(c_idx).inner * (ctr.children.len().saturating_sub(1));
+        let edges = self.edges(c_idx);
         let available_area = match strat {
             LayoutStrategy::Vertical => AreaSize {
                 height: ctr_bounds
                     .content
                     .height
-                    .saturating_sub(total_inter + 2 * ctr.padding),
-                width: ctr_bounds.content.width.saturating_sub(2 * ctr.padding),
+                    .saturating_sub(total_inter + edges.top + edges.bottom),
+                width: ctr_bounds
+                    .content
+                    .width
+                    .saturating_sub(edges.left + edges.right),
             },
             LayoutStrategy::Horizontal => AreaSize {
-                height: ctr_bounds.content.height.saturating_sub(2 * ctr.padding),
+                height: ctr_bounds
+                    .content
+                    .height
+                    .saturating_sub(edges.top + edges.bottom),
                 width: ctr_bounds
                     .content
                     .width
-                    .saturating_sub(total_inter + 2 * ctr.padding),
+                    .saturating_sub(total_inter + edges.left + edges.right),
             },
         };
         available_area
@@ -615,34 +1156,43 @@ where
             ItemIdx::Window(_) => return,
         };
         let available_area = self.ctr_available_area(c_idx);
+        let inter = self.gaps(c_idx).inner;
+        let edges = self.edges(c_idx);
         let ctr = &self.containers[&c_idx];
         let strat = ctr.strategy;
         let ctr_bounds = ctr.bounds;
         let begin = match strat {
-            LayoutStrategy::Vertical => ctr_bounds.position.y + ctr.padding,
-            LayoutStrategy::Horizontal => ctr_bounds.position.x + ctr.padding,
+            LayoutStrategy::Vertical => ctr_bounds.position.y + edges.top,
+            LayoutStrategy::Horizontal => ctr_bounds.position.x + edges.left,
         };
         let total_weight: f64 = ctr.children.iter().map(|(weight, _)| weight).sum();
         let mut next_window_origin = ctr_bounds.position;
-        next_window_origin.x += ctr.padding;
-        next_window_origin.y += ctr.padding;
-        let inter = ctr.inter;
+        next_window_origin.x += edges.left;
+        next_window_origin.y += edges.top;
         let mut to_fix = vec![];
         let mut cumsum = 0.0;
-        for &(weight, child) in ctr.children.iter() {
+        for (i, &(weight, child)) in ctr.children.iter().enumerate() {
             let normalized: f64 = weight / total_weight;
             cumsum += normalized;
+            if i == ctr.children.len() - 1 {
+                // Don't let rounding error leave a gap after the last child.
+                cumsum = 1.0;
+            }
             let old_bounds = self.bounds(child);
+            // Where this child ends, past the gaps before it.
+            let gaps_before = i * inter;
             let content = match strat {
                 LayoutStrategy::Vertical => {
-                    let new_y = (begin as f64 + cumsum * available_area.height as f64) as usize;
+                    let new_y = (begin as f64 + cumsum * available_area.height as f64) as usize
+                        + gaps_before;
                     AreaSize {
                         height: new_y - next_window_origin.y,
                         width: available_area.width,
                     }
                 }
                 LayoutStrategy::Horizontal => {
-                    let new_x = (begin as f64 + cumsum * available_area.width as f64) as usize;
+                    let new_x = (begin as f64 + cumsum * available_area.width as f64) as usize
+                        + gaps_before;
                     AreaSize {
                         height: available_area.height,
                         width: new_x - next_window_origin.x,
@@ -739,7 +1289,7 @@ where
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChildLocation {
     pub container: usize,
     pub index: usize,
@@ -757,6 +1307,7 @@ where
         }
         let mut out = vec![];
         self.layout(ItemIdx::Container(c_idx), &mut out);
+        self.debug_check_invariants();
         out
     }
     pub fn index_in_parent(&self, item: ItemIdx) -> Option<usize> {
@@ -802,17 +1353,65 @@ where
             idx,
             Container {
                 bounds,
-                inter: 0,
+                gaps: None,
                 data,
                 parent: None,
                 strategy: LayoutStrategy::Horizontal,
-                padding: self.default_padding,
+                padding: None,
                 children: vec![],
             },
         );
+        self.roots.insert(idx, Default::default());
+        self.debug_check_invariants();
         idx
     }
-    pub fn new(cctor: CCtor, default_padding: usize) -> Self {
+    /// All roots, in allocation order.
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.roots.keys().copied()
+    }
+    pub fn root_name(&self, root: usize) -> Option<&str> {
+        self.roots[&root].name.as_deref()
+    }
+    pub fn set_root_name(&mut self, root: usize, name: Option<String>) {
+        self.roots.get_mut(&root).expect("not a root").name = name;
+    }
+    /// Remember `item` as the most recently focused item of its root.
+    pub fn note_focused(&mut self, item: ItemIdx) {
+        if let Some(root) = self.root_of(item) {
+            self.roots.get_mut(&root).unwrap().last_focused = Some(item);
+        }
+    }
+    /// The most recently focused item of `root`, if it still exists and hasn't moved elsewhere.
+    pub fn last_focused(&self, root: usize) -> Option<ItemIdx> {
+        let item = self.roots[&root].last_focused?;
+        (self.exists(item) && self.root_of(item) == Some(root)).then_some(item)
+    }
+    /// Destroy `root`. Its children are moved to the end of `rehome` if given,
+    /// and are destroyed along with it otherwise.
+    pub fn destroy_root(&mut self, root: usize, rehome: Option<usize>) -> Vec<LayoutAction<W, C>> {
+        assert!(self.roots.contains_key(&root), "{root} is not a root");
+        assert_ne!(
+            Some(root),
+            rehome,
+            "can't rehome a root's children into itself"
+        );
+        let mut result = vec![];
+        if let Some(rehome) = rehome {
+            let children = self
+                .children(root)
+                .iter()
+                .map(|&(_weight, child)| child)
+                .collect::<Vec<_>>();
+            for child in children {
+                result.extend(self.move_to_root(child, rehome));
+            }
+        }
+        result.extend(self.remove_subtree(ItemIdx::Container(root)));
+        self.roots.remove(&root);
+        self.debug_check_invariants();
+        result
+    }
+    pub fn new(cctor: CCtor, default_padding: Padding, default_gaps: Gaps) -> Self {
         // let root_data = cctor.construct();
         let container_idgen = 42; // Not 0, in order to crash loudly if we're doing something special on 0.
                                   // let first_root_id = container_idgen.next_id();
@@ -828,9 +1427,11 @@ where
         Self {
             windows: Default::default(),
             containers: Default::default(),
-            // roots,
+            roots: Default::default(),
             cctor: Some(cctor),
             default_padding,
+            default_gaps,
+            smart_gaps: false,
             container_idgen,
             window_idgen: 123, // Not 0, see above
         }
@@ -848,6 +1449,109 @@ where
         // );
         // this
     }
+    pub fn constructor_mut(&mut self) -> &mut CCtor {
+        self.cctor.as_mut().expect("Must set cctor!")
+    }
+    /// Set the padding of every container without its own, including ones
+    /// made later, and lay out everything again.
+    pub fn set_default_padding(&mut self, padding: Padding) -> Vec<LayoutAction<W, C>> {
+        self.default_padding = padding;
+        self.layout_all()
+    }
+    /// Give `c_idx` its own padding, or with `None` the default again.
+    pub fn set_padding(
+        &mut self,
+        c_idx: usize,
+        padding: Option<Padding>,
+    ) -> Vec<LayoutAction<W, C>> {
+        self.containers.get_mut(&c_idx).unwrap().padding = padding;
+        let mut out = vec![];
+        self.layout(ItemIdx::Container(c_idx), &mut out);
+        self.debug_check_invariants();
+        out
+    }
+    /// The padding `c_idx` is laid out with: its own or the default.
+    pub fn padding(&self, c_idx: usize) -> Padding {
+        self.containers[&c_idx]
+            .padding
+            .unwrap_or(self.default_padding)
+    }
+    /// `c_idx`'s own padding, if it has any.
+    pub fn own_padding(&self, c_idx: usize) -> Option<Padding> {
+        self.containers[&c_idx].padding
+    }
+    pub fn root_margins(&self, root: usize) -> Padding {
+        self.roots[&root].margins
+    }
+    pub fn set_root_margins(&mut self, root: usize, margins: Padding) -> Vec<LayoutAction<W, C>> {
+        self.roots.get_mut(&root).unwrap().margins = margins;
+        let mut out = vec![];
+        self.layout(ItemIdx::Container(root), &mut out);
+        self.debug_check_invariants();
+        out
+    }
+    /// The space between `c_idx`'s edges and its children: its padding,
+    /// and for a root, its margins and outer gap.
+    pub fn edges(&self, c_idx: usize) -> Padding {
+        let padding = self.padding(c_idx);
+        match self.roots.get(&c_idx) {
+            Some(info) => padding + info.margins + Padding::uniform(self.gaps(c_idx).outer),
+            None => padding,
+        }
+    }
+    /// Set the gaps of every container without its own, including ones
+    /// made later, and lay out everything again.
+    pub fn set_default_gaps(&mut self, gaps: Gaps) -> Vec<LayoutAction<W, C>> {
+        self.default_gaps = gaps;
+        self.layout_all()
+    }
+    /// Set whether containers with only one child have no gaps,
+    /// and lay out everything again.
+    pub fn set_smart_gaps(&mut self, smart: bool) -> Vec<LayoutAction<W, C>> {
+        self.smart_gaps = smart;
+        self.layout_all()
+    }
+    fn layout_all(&mut self) -> Vec<LayoutAction<W, C>> {
+        // A container's gaps can change without its bounds changing,
+        // so every one is laid out, not just those that moved.
+        let containers = self
+            .roots()
+            .flat_map(|root| self.iter_descendants(ItemIdx::Container(root)))
+            .filter(|item| matches!(item, ItemIdx::Container(_)))
+            .collect::<Vec<_>>();
+        let mut out = vec![];
+        for ctr in containers {
+            self.layout(ctr, &mut out);
+        }
+        self.debug_check_invariants();
+        out
+    }
+    /// Give `c_idx` its own gaps, or with `None` the default ones again.
+    pub fn set_gaps(&mut self, c_idx: usize, gaps: Option<Gaps>) -> Vec<LayoutAction<W, C>> {
+        self.containers.get_mut(&c_idx).unwrap().gaps = gaps;
+        let mut out = vec![];
+        self.layout(ItemIdx::Container(c_idx), &mut out);
+        self.debug_check_invariants();
+        out
+    }
+    /// The gaps `c_idx` is laid out with: its own or the default ones,
+    /// with no outer gap unless it's a root, and none at all if it has
+    /// only one child and smart gaps are on.
+    pub fn gaps(&self, c_idx: usize) -> Gaps {
+        let ctr = &self.containers[&c_idx];
+        if self.smart_gaps && ctr.children.len() < 2 {
+            return Gaps::default();
+        }
+        let gaps = ctr.gaps.unwrap_or(self.default_gaps);
+        Gaps {
+            inner: gaps.inner,
+            outer: if ctr.parent.is_none() { gaps.outer } else { 0 },
+        }
+    }
+    /// `c_idx`'s own gaps, if it has any, as given to `set_gaps`.
+    pub fn own_gaps(&self, c_idx: usize) -> Option<Gaps> {
+        self.containers[&c_idx].gaps
+    }
     pub fn windows<'a>(&'a self) -> impl Iterator<Item = &'a Window<W>> {
         self.windows.values()
     }
@@ -866,6 +1570,7 @@ where
             idx: ItemIdx::Container(root_ctr),
             bounds,
         });
+        self.debug_check_invariants();
         out
     }
     pub fn parent_container(&self, item: ItemIdx) -> Option<usize> {
@@ -908,6 +1613,16 @@ where
             }
         }
     }
+    /// If all of `c_idx`'s children have zero weight (e.g., because the ones that didn't
+    /// were removed), give them equal weights, so that the total stays positive.
+    fn fix_zero_weights(&mut self, c_idx: usize) {
+        let children = &mut self.containers.get_mut(&c_idx).unwrap().children;
+        if children.iter().all(|&(weight, _child)| weight == 0.0) {
+            for (weight, _child) in children {
+                *weight = 1.0;
+            }
+        }
+    }
     /// Returns the container that remains (i.e., the GP) if a fuse was done
     fn fuse_if_necessary(
         &mut self,
@@ -936,12 +1651,11 @@ where
         }
         None
     }
-    pub fn destroy(&mut self, item: ItemIdx) -> Vec<LayoutAction<W, C>> {
+    /// Remove `item` and its descendants from the layout, without fixing up its parent.
+    fn remove_subtree(&mut self, item: ItemIdx) -> Vec<LayoutAction<W, C>> {
         let to_destroy = self.iter_descendants(item).collect::<Vec<_>>();
-        let parent = self.parent_container(item);
-        let index_in_parent = self.index_in_parent(item);
         // Remove items from the layout, and take their data for passing back up
-        let mut result = to_destroy
+        to_destroy
             .iter()
             .copied()
             .map(|descendant| {
@@ -955,7 +1669,20 @@ where
                 };
                 LayoutAction::ItemDestroyed { item }
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
+    pub fn destroy(&mut self, item: ItemIdx) -> Vec<LayoutAction<W, C>> {
+        let parent = self.parent_container(item);
+        let index_in_parent = self.index_in_parent(item);
+        let bounds = self.bounds(item);
+        let (gaps, padding) = match item {
+            ItemIdx::Container(c_idx) => {
+                let ctr = &self.containers[&c_idx];
+                (ctr.gaps, ctr.padding)
+            }
+            ItemIdx::Window(_) => (None, None),
+        };
+        let mut result = self.remove_subtree(item);
         match parent {
             None => {
                 // we destroyed a root, but roots must be destroyed explicitly.
@@ -968,10 +1695,11 @@ where
                         strategy: LayoutStrategy::Horizontal,
                         children: vec![],
                         parent: None,
-                        inter: Default::default(),
-                        bounds: self.bounds(item),
+                        // A recreated root keeps its own gaps and padding.
+                        gaps,
+                        bounds,
                         data: self.cctor.as_mut().expect("Must set cctor!").construct(),
-                        padding: self.default_padding,
+                        padding,
                     },
                 );
             }
@@ -979,6 +1707,7 @@ where
                 let index_in_parent = index_in_parent.unwrap();
                 let parent_ctr = self.containers.get_mut(&parent).unwrap();
                 parent_ctr.children.remove(index_in_parent);
+                self.fix_zero_weights(parent);
                 // fuse if necessary
                 if let Some(grandparent) = self.fuse_if_necessary(parent, &mut result) {
                     parent = grandparent;
@@ -986,6 +1715,7 @@ where
                 self.layout(ItemIdx::Container(parent), &mut result);
             }
         };
+        self.debug_check_invariants();
         result
     }
     pub fn is_ancestor(&self, ancestor: ItemIdx, mut descendant: ItemIdx) -> bool {
@@ -1025,14 +1755,17 @@ where
                 data,
             },
         );
+        self.debug_check_invariants();
         next_idx
     }
     pub fn r#move(&mut self, from: ItemIdx, to: MoveCursor) -> Vec<LayoutAction<W, C>> {
         if self.is_ancestor(from, to.item()) {
-            panic!()
+            panic!("Can't move {from:?} into itself, at {to:?}")
+        }
+        if matches!(from, ItemIdx::Container(c_idx) if self.roots.contains_key(&c_idx)) {
+            panic!("Can't move root {from:?}")
         }
-        // we know `from` is not a root, because of the ancestry check above.
-        // So the unwrap is safe.
+        // `from` has no parent only if it's a window fresh from `alloc_window`.
         let mut from_parent = self.parent_container(from);
         let mut result = vec![];
         let idx_in_parent = if let Some(from_parent) = from_parent {
@@ -1043,6 +1776,7 @@ where
                 .position(|&(_weight, child)| child == from)
                 .unwrap();
             parent_ctr.children.remove(idx_in_parent);
+            self.fix_zero_weights(from_parent);
             Some(idx_in_parent)
         } else {
             None
@@ -1074,16 +1808,389 @@ where
             }
         }
         self.layout(ItemIdx::Container(insert_modified), &mut result);
-        if from_parent.is_some()
-            && !self.is_ancestor(
-                ItemIdx::Container(insert_modified),
-                ItemIdx::Container(from_parent.unwrap()),
-            )
-        {
-            self.layout(ItemIdx::Container(from_parent.unwrap()), &mut result);
+        if let Some(from_parent) = from_parent {
+            // Even if it's under `insert_modified`, that layout only reaches it
+            // if its bounds changed, which they needn't have.
+            self.layout(ItemIdx::Container(from_parent), &mut result);
         }
+        self.debug_check_invariants();
         result
     }
+    pub fn snapshot(&self, root: usize) -> Snapshot {
+        Snapshot {
+            root,
+            tree: self.snapshot_node(ItemIdx::Container(root)),
+        }
+    }
+    fn snapshot_node(&self, item: ItemIdx) -> SnapshotNode {
+        match item {
+            ItemIdx::Window(w_idx) => SnapshotNode::Window(w_idx),
+            ItemIdx::Container(c_idx) => {
+                let ctr = &self.containers[&c_idx];
+                SnapshotNode::Container {
+                    c_idx,
+                    strategy: ctr.strategy,
+                    children: ctr
+                        .children
+                        .iter()
+                        .map(|&(weight, child)| (weight, self.snapshot_node(child)))
+                        .collect(),
+                }
+            }
+        }
+    }
+    /// Rebuild the snapshot's root in the shape it recorded.
+    ///
+    /// Items still in the root are reused along with their data.
+    /// Items that no longer exist are recreated, taking window data from `new_window`,
+    /// which is given the index each recreated window keeps;
+    /// items that have since moved to another root are left there.
+    /// Items in the root that aren't part of the snapshot are destroyed.
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot,
+        mut new_window: impl FnMut(usize) -> W,
+    ) -> Vec<LayoutAction<W, C>> {
+        let root = snapshot.root;
+        let SnapshotNode::Container {
+            strategy, children, ..
+        } = &snapshot.tree
+        else {
+            panic!("Snapshot of a window");
+        };
+        let mut result = vec![];
+        let mut detached = self
+            .iter_descendants(ItemIdx::Container(root))
+            .skip(1)
+            .collect::<HashSet<_>>();
+        let children = children
+            .iter()
+            .filter_map(|(weight, child)| {
+                self.rebuild_node(child, root, &mut detached, &mut new_window)
+                    .map(|child| (*weight, child))
+            })
+            .collect::<Vec<_>>();
+        for &(_weight, child) in &children {
+            self.set_parent_unchecked(child, Some(root));
+        }
+        let root_ctr = self.containers.get_mut(&root).unwrap();
+        root_ctr.strategy = *strategy;
+        root_ctr.children = children;
+        self.fix_zero_weights(root);
+        // Whatever wasn't claimed back is gone.
+        for item in detached {
+            let item = match item {
+                ItemIdx::Container(c_idx) => {
+                    ItemAndData::Container(c_idx, self.containers.remove(&c_idx).unwrap().data)
+                }
+                ItemIdx::Window(w_idx) => {
+                    ItemAndData::Window(w_idx, self.windows.remove(&w_idx).unwrap().data)
+                }
+            };
+            result.push(LayoutAction::ItemDestroyed { item });
+        }
+        // `layout` only descends into containers whose bounds changed,
+        // but reused containers may have new children regardless.
+        let containers = self
+            .iter_descendants(ItemIdx::Container(root))
+            .filter(|item| matches!(item, ItemIdx::Container(_)))
+            .collect::<Vec<_>>();
+        for ctr in containers {
+            self.layout(ctr, &mut result);
+        }
+        self.debug_check_invariants();
+        result
+    }
+    /// Returns the item standing in for `node` under `root`, or `None` if nothing does.
+    /// Containers left with fewer than two children are elided, as in `fuse_if_necessary`.
+    fn rebuild_node(
+        &mut self,
+        node: &SnapshotNode,
+        root: usize,
+        detached: &mut HashSet<ItemIdx>,
+        new_window: &mut impl FnMut(usize) -> W,
+    ) -> Option<ItemIdx> {
+        let empty_bounds = WindowBounds {
+            content: Default::default(),
+            position: Position {
+                x: 0,
+                y: 0,
+                root_ctr: root,
+            },
+        };
+        match node {
+            &SnapshotNode::Window(w_idx) => {
+                let item = ItemIdx::Window(w_idx);
+                if detached.remove(&item) {
+                    Some(item)
+                } else if let Entry::Vacant(entry) = self.windows.entry(w_idx) {
+                    entry.insert(Window {
+                        bounds: empty_bounds,
+                        parent: None,
+                        data: new_window(w_idx),
+                    });
+                    Some(item)
+                } else {
+                    None
+                }
+            }
+            SnapshotNode::Container {
+                c_idx,
+                strategy,
+                children,
+            } => {
+                let mut children = children
+                    .iter()
+                    .filter_map(|(weight, child)| {
+                        self.rebuild_node(child, root, detached, new_window)
+                            .map(|child| (*weight, child))
+                    })
+                    .collect::<Vec<_>>();
+                if children.len() < 2 {
+                    return children.pop().map(|(_weight, child)| child);
+                }
+                let c_idx = if detached.remove(&ItemIdx::Container(*c_idx)) {
+                    *c_idx
+                } else {
+                    let c_idx = if self.containers.contains_key(c_idx) {
+                        // It's in use elsewhere now; make a new one.
+                        self.container_idgen.next_id()
+                    } else {
+                        *c_idx
+                    };
+                    self.containers.insert(
+                        c_idx,
+                        Container {
+                            strategy: *strategy,
+                            children: vec![],
+                            parent: None,
+                            bounds: empty_bounds,
+                            gaps: None,
+                            data: self.cctor.as_mut().expect("Must set cctor!").construct(),
+                            padding: None,
+                        },
+                    );
+                    c_idx
+                };
+                for &(_weight, child) in &children {
+                    self.set_parent_unchecked(child, Some(c_idx));
+                }
+                let ctr = self.containers.get_mut(&c_idx).unwrap();
+                ctr.strategy = *strategy;
+                ctr.children = children;
+                self.fix_zero_weights(c_idx);
+                Some(ItemIdx::Container(c_idx))
+            }
+        }
+    }
+    /// `root`'s tree, with what `window` makes of each window's index and data.
+    pub fn export<E>(
+        &self,
+        root: usize,
+        mut window: impl FnMut(usize, &W) -> E,
+    ) -> ExportedRoot<E> {
+        ExportedRoot {
+            version: EXPORT_VERSION,
+            root,
+            name: self.roots[&root].name.clone(),
+            point: None,
+            cursor: None,
+            tree: self.export_node(ItemIdx::Container(root), &mut window),
+        }
+    }
+    fn export_node<E>(
+        &self,
+        item: ItemIdx,
+        window: &mut impl FnMut(usize, &W) -> E,
+    ) -> ExportedNode<E> {
+        match item {
+            ItemIdx::Window(w_idx) => {
+                let w = &self.windows[&w_idx];
+                ExportedNode::Window {
+                    id: w_idx,
+                    bounds: w.bounds,
+                    data: window(w_idx, &w.data),
+                }
+            }
+            ItemIdx::Container(c_idx) => {
+                let ctr = &self.containers[&c_idx];
+                ExportedNode::Container {
+                    id: c_idx,
+                    strategy: ctr.strategy,
+                    bounds: ctr.bounds,
+                    children: ctr
+                        .children
+                        .iter()
+                        .map(|&(weight, child)| (weight, self.export_node(child, window)))
+                        .collect(),
+                }
+            }
+        }
+    }
+    /// Build a new root in the shape of `exported`, sized like the exported
+    /// root, and with data for each window from `new_window`, which is given
+    /// the window's exported id and data. Everything gets a new index.
+    /// As in `restore`, containers left with fewer than two children are elided.
+    pub fn import<E>(
+        &mut self,
+        exported: &ExportedRoot<E>,
+        mut new_window: impl FnMut(usize, &E) -> W,
+    ) -> Result<Imported<W, C>, LayoutError>
+    where
+        W: std::fmt::Debug,
+        C: std::fmt::Debug,
+    {
+        if exported.version != EXPORT_VERSION {
+            return Err(LayoutError::UnsupportedExportVersion(exported.version));
+        }
+        if self.cctor.is_none() {
+            return Err(LayoutError::NoConstructor);
+        }
+        let ExportedNode::Container {
+            strategy,
+            bounds,
+            children,
+            ..
+        } = &exported.tree
+        else {
+            return Err(LayoutError::BadExport(ItemIdx::Window(exported.root)));
+        };
+        Self::check_export(&exported.tree, &mut BTreeSet::new())?;
+        let root = self.alloc_root();
+        self.set_root_name(root, exported.name.clone());
+        let mut windows = BTreeMap::new();
+        let children = children
+            .iter()
+            .filter_map(|(weight, child)| {
+                self.import_node(child, root, &mut windows, &mut new_window)
+                    .map(|child| (*weight, child))
+            })
+            .collect::<Vec<_>>();
+        for &(_weight, child) in &children {
+            self.set_parent_unchecked(child, Some(root));
+        }
+        let root_ctr = self.containers.get_mut(&root).unwrap();
+        root_ctr.strategy = *strategy;
+        root_ctr.children = children;
+        self.fix_zero_weights(root);
+        let mut bounds = *bounds;
+        bounds.position.root_ctr = root;
+        let actions = self.resize(bounds);
+        self.debug_check_invariants();
+        Ok(Imported {
+            root,
+            windows,
+            actions,
+        })
+    }
+    /// Whether the weights in `node` would pass `check_invariants`, and its
+    /// windows' ids are distinct from each other and from `window_ids`.
+    fn check_export<E>(
+        node: &ExportedNode<E>,
+        window_ids: &mut BTreeSet<usize>,
+    ) -> Result<(), LayoutError> {
+        match node {
+            ExportedNode::Window { id, .. } => {
+                if !window_ids.insert(*id) {
+                    return Err(LayoutError::BadExport(ItemIdx::Window(*id)));
+                }
+            }
+            ExportedNode::Container { id, children, .. } => {
+                let bad = |w: f64| !w.is_finite() || w < 0.0;
+                if children.iter().any(|&(weight, _)| bad(weight))
+                    || (!children.is_empty() && children.iter().all(|&(weight, _)| weight == 0.0))
+                {
+                    return Err(LayoutError::BadExport(ItemIdx::Container(*id)));
+                }
+                for (_weight, child) in children {
+                    Self::check_export(child, window_ids)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn import_node<E>(
+        &mut self,
+        node: &ExportedNode<E>,
+        root: usize,
+        windows: &mut BTreeMap<usize, usize>,
+        new_window: &mut impl FnMut(usize, &E) -> W,
+    ) -> Option<ItemIdx> {
+        let empty_bounds = WindowBounds {
+            content: Default::default(),
+            position: Position {
+                x: 0,
+                y: 0,
+                root_ctr: root,
+            },
+        };
+        match node {
+            ExportedNode::Window { id, data, .. } => {
+                // Not `alloc_window`, which would check invariants while
+                // containers made so far have no parent.
+                let w_idx = self.window_idgen.next_id();
+                self.windows.insert(
+                    w_idx,
+                    Window {
+                        bounds: empty_bounds,
+                        parent: None,
+                        data: new_window(*id, data),
+                    },
+                );
+                windows.insert(*id, w_idx);
+                Some(ItemIdx::Window(w_idx))
+            }
+            ExportedNode::Container {
+                strategy, children, ..
+            } => {
+                let mut children = children
+                    .iter()
+                    .filter_map(|(weight, child)| {
+                        self.import_node(child, root, windows, new_window)
+                            .map(|child| (*weight, child))
+                    })
+                    .collect::<Vec<_>>();
+                if children.len() < 2 {
+                    return children.pop().map(|(_weight, child)| child);
+                }
+                let c_idx = self.container_idgen.next_id();
+                self.containers.insert(
+                    c_idx,
+                    Container {
+                        strategy: *strategy,
+                        children: vec![],
+                        parent: None,
+                        bounds: empty_bounds,
+                        gaps: None,
+                        data: self.cctor.as_mut().expect("Must set cctor!").construct(),
+                        padding: None,
+                    },
+                );
+                for &(_weight, child) in &children {
+                    self.set_parent_unchecked(child, Some(c_idx));
+                }
+                let ctr = self.containers.get_mut(&c_idx).unwrap();
+                ctr.children = children;
+                self.fix_zero_weights(c_idx);
+                Some(ItemIdx::Container(c_idx))
+            }
+        }
+    }
+    /// Move `item` and its descendants to the end of `root`.
+    pub fn move_to_root(&mut self, item: ItemIdx, root: usize) -> Vec<LayoutAction<W, C>> {
+        assert!(
+            self.containers[&root].parent.is_none(),
+            "{root} is not a root"
+        );
+        let index = self.containers[&root].children.len();
+        self.r#move(
+            item,
+            MoveCursor::Into {
+                container: root,
+                index,
+            },
+        )
+    }
     pub fn navigate(
         &self,
         from: ItemIdx,
@@ -1246,6 +2353,17 @@ where
     pub fn children(&self, container: usize) -> &[(f64, ItemIdx)] {
         &self.containers[&container].children
     }
+    /// The root container that `item` belongs to,
+    /// or `None` for a window that hasn't been placed yet.
+    pub fn root_of(&self, mut item: ItemIdx) -> Option<usize> {
+        while let Some(parent) = self.parent_container(item) {
+            item = ItemIdx::Container(parent);
+        }
+        match item {
+            ItemIdx::Container(c_idx) => Some(c_idx),
+            ItemIdx::Window(_) => None,
+        }
+    }
     pub fn nearest_container(&self, item: ItemIdx) -> usize {
         match item {
             ItemIdx::Container(c_idx) => c_idx,
@@ -1266,8 +2384,8 @@ where
     /// Get the bounds of the gap before element `index` in the container.
     /// `index` may be equal to the container's length, in which case
     /// this function returns the gap at the end.
-    pub fn inter_bounds(&self, container: usize, index: usize) -> WindowBounds {
-        let container = &self.containers[&container];
+    pub fn inter_bounds(&self, container_idx: usize, index: usize) -> WindowBounds {
+        let container = &self.containers[&container_idx];
         assert!(index <= container.children.len());
         if container.children.is_empty() {
             return container.bounds;
@@ -1298,7 +2416,10 @@ where
                 },
             };
         }
-        let total_inter = container.inter * container.children.len().saturating_sub(1);
+        let inner = self.gaps(container_idx).inner;
+        let edges = self.edges(container_idx);
+        let (main_before, main_after) = edges.main(container.strategy);
+        let total_inter = inner * container.children.len().saturating_sub(1);
         let total_weight: f64 = container
             .children
             .iter()
@@ -1308,36 +2429,43 @@ where
             LayoutStrategy::Horizontal => container.bounds.content.width,
             LayoutStrategy::Vertical => container.bounds.content.height,
         };
-        // XXX - padding should be configurable on all four sides
-        let content_size = main_dim_bound - total_inter - 2 * container.padding;
+        let content_size = main_dim_bound.saturating_sub(total_inter + main_before + main_after);
         // The distance from the
         // beginning of the container
         // to the end of the `i-1`th child
         // (or 0, when i is 0)
-        let mut cum_distance = container.padding as f64;
+        let mut cum_distance = main_before as f64;
         for i in 0..index {
             let normalized = container.children[i].0 / total_weight;
-            cum_distance += normalized * (content_size as f64) + container.inter as f64;
+            cum_distance += normalized * (content_size as f64) + inner as f64;
         }
         match container.strategy {
             LayoutStrategy::Horizontal => WindowBounds {
                 content: AreaSize {
-                    height: container.bounds.content.height - 2 * container.padding,
-                    width: container.inter,
+                    height: container
+                        .bounds
+                        .content
+                        .height
+                        .saturating_sub(edges.top + edges.bottom),
+                    width: inner,
                 },
                 position: Position {
                     x: container.bounds.position.x + cum_distance as usize,
-                    y: container.bounds.position.y + container.padding,
+                    y: container.bounds.position.y + edges.top,
                     root_ctr: container.bounds.position.root_ctr,
                 },
             },
             LayoutStrategy::Vertical => WindowBounds {
                 content: AreaSize {
-                    height: container.inter,
-                    width: container.bounds.content.width - 2 * container.padding,
+                    height: inner,
+                    width: container
+                        .bounds
+                        .content
+                        .width
+                        .saturating_sub(edges.left + edges.right),
                 },
                 position: Position {
-                    x: container.bounds.position.x + container.padding,
+                    x: container.bounds.position.x + edges.left,
                     y: container.bounds.position.y + cum_distance as usize,
                     root_ctr: container.bounds.position.root_ctr,
                 },
@@ -1351,6 +2479,265 @@ where
             ItemIdx::Window(w_idx) => self.windows.get(&w_idx).is_some(),
         }
     }
+    pub fn check_item(&self, item: ItemIdx) -> Result<(), LayoutError> {
+        if self.exists(item) {
+            Ok(())
+        } else {
+            Err(LayoutError::NoSuchItem(item))
+        }
+    }
+    pub fn check_root(&self, root: usize) -> Result<(), LayoutError> {
+        self.check_item(ItemIdx::Container(root))?;
+        if self.roots.contains_key(&root) {
+            Ok(())
+        } else {
+            Err(LayoutError::NotARoot(root))
+        }
+    }
+    /// Like `check_item`, but roots aren't allowed.
+    pub fn check_non_root(&self, item: ItemIdx) -> Result<(), LayoutError> {
+        self.check_item(item)?;
+        match item {
+            ItemIdx::Container(c_idx) if self.roots.contains_key(&c_idx) => {
+                Err(LayoutError::IsARoot(c_idx))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn check_cursor(&self, cursor: MoveCursor) -> Result<(), LayoutError> {
+        if self.is_cursor_valid(cursor) {
+            Ok(())
+        } else {
+            Err(LayoutError::InvalidCursor(cursor))
+        }
+    }
+    pub fn checked_bounds(&self, item: ItemIdx) -> Result<WindowBounds, LayoutError> {
+        self.try_bounds(item).ok_or(LayoutError::NoSuchItem(item))
+    }
+    pub fn checked_children(&self, container: usize) -> Result<&[(f64, ItemIdx)], LayoutError> {
+        self.check_item(ItemIdx::Container(container))?;
+        Ok(self.children(container))
+    }
+    pub fn checked_n_children(&self, item: ItemIdx) -> Result<usize, LayoutError> {
+        self.check_item(item)?;
+        Ok(self.n_children(item))
+    }
+    pub fn checked_parent_container(&self, item: ItemIdx) -> Result<Option<usize>, LayoutError> {
+        self.check_item(item)?;
+        Ok(self.parent_container(item))
+    }
+    pub fn checked_item_from_child_location(
+        &self,
+        cl: ChildLocation,
+    ) -> Result<Option<ItemIdx>, LayoutError> {
+        let n_children = self.checked_n_children(ItemIdx::Container(cl.container))?;
+        if cl.index > n_children {
+            return Err(LayoutError::NoSuchChild(cl));
+        }
+        Ok(self.item_from_child_location(cl))
+    }
+    pub fn checked_alloc_root(&mut self) -> Result<usize, LayoutError> {
+        if self.cctor.is_none() {
+            return Err(LayoutError::NoConstructor);
+        }
+        Ok(self.alloc_root())
+    }
+    pub fn checked_move(
+        &mut self,
+        from: ItemIdx,
+        to: MoveCursor,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_non_root(from)?;
+        self.check_cursor(to)?;
+        if self.is_ancestor(from, to.item()) {
+            return Err(LayoutError::MoveIntoSelf {
+                item: from,
+                cursor: to,
+            });
+        }
+        if matches!(to, MoveCursor::Split { .. }) && self.cctor.is_none() {
+            return Err(LayoutError::NoConstructor);
+        }
+        Ok(self.r#move(from, to))
+    }
+    pub fn checked_destroy(
+        &mut self,
+        item: ItemIdx,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_item(item)?;
+        if matches!(item, ItemIdx::Container(c_idx) if self.roots.contains_key(&c_idx))
+            && self.cctor.is_none()
+        {
+            return Err(LayoutError::NoConstructor);
+        }
+        Ok(self.destroy(item))
+    }
+    pub fn checked_set_content_length(
+        &mut self,
+        item: ItemIdx,
+        new_length: usize,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_item(item)?;
+        Ok(self.set_content_length(item, new_length))
+    }
+    pub fn checked_equalize_container_children(
+        &mut self,
+        c_idx: usize,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_item(ItemIdx::Container(c_idx))?;
+        Ok(self.equalize_container_children(c_idx))
+    }
+    pub fn checked_move_to_root(
+        &mut self,
+        item: ItemIdx,
+        root: usize,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_non_root(item)?;
+        self.check_root(root)?;
+        Ok(self.move_to_root(item, root))
+    }
+    /// Check that `destroy_root(root, rehome)` would succeed.
+    pub fn check_destroy_root(
+        &self,
+        root: usize,
+        rehome: Option<usize>,
+    ) -> Result<(), LayoutError> {
+        self.check_root(root)?;
+        if let Some(rehome) = rehome {
+            self.check_root(rehome)?;
+            if rehome == root {
+                return Err(LayoutError::MoveIntoSelf {
+                    item: ItemIdx::Container(root),
+                    cursor: MoveCursor::Into {
+                        container: root,
+                        index: 0,
+                    },
+                });
+            }
+        }
+        Ok(())
+    }
+    pub fn checked_destroy_root(
+        &mut self,
+        root: usize,
+        rehome: Option<usize>,
+    ) -> Result<Vec<LayoutAction<W, C>>, LayoutError> {
+        self.check_destroy_root(root, rehome)?;
+        Ok(self.destroy_root(root, rehome))
+    }
+    pub fn checked_alloc_window(&mut self, data: W, root_ctr: usize) -> Result<usize, LayoutError> {
+        self.check_root(root_ctr)?;
+        Ok(self.alloc_window(data, root_ctr))
+    }
+    /// Check the structural invariants the rest of `Layout` relies on,
+    /// returning the first violation found.
+    ///
+    /// Windows without a parent are allowed; they haven't been placed yet.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        for &root in self.roots.keys() {
+            if self.containers.get(&root).map(|ctr| ctr.parent.is_some()) != Some(false) {
+                return Err(InvariantViolation::BadRoot { root });
+            }
+        }
+        let mut seen = HashSet::new();
+        for (&c_idx, ctr) in &self.containers {
+            match ctr.parent {
+                None if !self.roots.contains_key(&c_idx) => {
+                    return Err(InvariantViolation::UnregisteredRoot { container: c_idx });
+                }
+                Some(_) if ctr.children.len() < 2 => {
+                    return Err(InvariantViolation::UnderfullContainer {
+                        container: c_idx,
+                        n_children: ctr.children.len(),
+                    });
+                }
+                _ => {}
+            }
+            let weights = ctr.children.iter().map(|&(weight, _)| weight);
+            let total: f64 = weights.clone().sum();
+            if !ctr.children.is_empty()
+                && (weights.clone().any(|w| !w.is_finite() || w < 0.0) || total <= 0.0)
+            {
+                return Err(InvariantViolation::BadWeights {
+                    container: c_idx,
+                    weights: weights.collect(),
+                });
+            }
+            for &(_weight, child) in &ctr.children {
+                if !self.exists(child) {
+                    return Err(InvariantViolation::MissingChild {
+                        container: c_idx,
+                        child,
+                    });
+                }
+                let parent = self.parent_container(child);
+                if parent != Some(c_idx) {
+                    return Err(InvariantViolation::WrongParent {
+                        item: child,
+                        container: c_idx,
+                        parent,
+                    });
+                }
+                if !seen.insert(child) {
+                    return Err(InvariantViolation::DuplicateChild { item: child });
+                }
+            }
+        }
+        let items = self
+            .windows
+            .iter()
+            .map(|(&w_idx, w)| (ItemIdx::Window(w_idx), w.parent, w.bounds))
+            .chain(
+                self.containers
+                    .iter()
+                    .map(|(&c_idx, c)| (ItemIdx::Container(c_idx), c.parent, c.bounds)),
+            );
+        for (item, parent, bounds) in items {
+            let Some(parent) = parent else {
+                if let ItemIdx::Container(c_idx) = item {
+                    if bounds.position.root_ctr != c_idx {
+                        return Err(InvariantViolation::WrongRootCtr {
+                            item,
+                            actual: c_idx,
+                            recorded: bounds.position.root_ctr,
+                        });
+                    }
+                }
+                continue;
+            };
+            if !self.containers.contains_key(&parent) || !seen.contains(&item) {
+                return Err(InvariantViolation::NotAmongChildren { item, parent });
+            }
+            // Every parent pointer checked so far matches a child list, so the
+            // only way not to reach a root is to go around in circles.
+            let mut root = parent;
+            let mut steps = 0;
+            while let Some(next) = self.containers[&root].parent {
+                root = next;
+                steps += 1;
+                if steps > self.containers.len() {
+                    return Err(InvariantViolation::ParentCycle { item });
+                }
+            }
+            if bounds.position.root_ctr != root {
+                return Err(InvariantViolation::WrongRootCtr {
+                    item,
+                    actual: root,
+                    recorded: bounds.position.root_ctr,
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Panic if an invariant is broken. Only checks in debug builds,
+    /// or with the `check-invariants` feature.
+    fn debug_check_invariants(&self) {
+        if cfg!(any(debug_assertions, feature = "check-invariants")) {
+            if let Err(violation) = self.check_invariants() {
+                panic!("Layout invariant violated: {violation}");
+            }
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
@@ -1362,3 +2749,29 @@ pub enum LayoutAction<W, C> {
     /// A window still exists, but is no longer visible.
     ItemHidden { idx: ItemIdx },
 }
+
+impl<W, C> LayoutAction<W, C> {
+    /// The item the action is about.
+    pub fn item(&self) -> ItemIdx {
+        match self {
+            LayoutAction::NewBounds { idx, .. } | LayoutAction::ItemHidden { idx } => *idx,
+            LayoutAction::ItemDestroyed { item } => item.item(),
+        }
+    }
+    /// This action without the data of any destroyed item, e.g. to report it.
+    pub fn without_data(&self) -> LayoutAction<(), ()> {
+        match *self {
+            LayoutAction::NewBounds { idx, bounds } => LayoutAction::NewBounds { idx, bounds },
+            LayoutAction::ItemDestroyed { ref item } => LayoutAction::ItemDestroyed {
+                item: match *item {
+                    ItemAndData::Window(idx, _) => ItemAndData::Window(idx, ()),
+                    ItemAndData::Container(idx, _) => ItemAndData::Container(idx, ()),
+                },
+            },
+            LayoutAction::ItemHidden { idx } => LayoutAction::ItemHidden { idx },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,929 @@
+//! Randomized tests for `Layout`: apply random sequences of operations,
+//! and check after every step that the tree is still well-formed
+//! and that each container's children exactly tile it.
+
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+
+use super::*;
+
+#[derive(Default)]
+struct UnitCtor;
+
+impl Constructor for UnitCtor {
+    type Item = ();
+
+    fn construct(&mut self) -> Self::Item {}
+}
+
+type TestLayout = Layout<(), (), UnitCtor>;
+
+const CASES: u64 = 300;
+const STEPS: usize = 200;
+
+#[derive(Debug)]
+enum Op {
+    Insert(MoveCursor),
+    Move(ItemIdx, MoveCursor),
+    Destroy(ItemIdx),
+    Resize(WindowBounds),
+    SetContentLength(ItemIdx, usize),
+    Equalize(usize),
+    SetDefaultPadding(Padding),
+    SetPadding(usize, Option<Padding>),
+    SetRootMargins(usize, Padding),
+    SetDefaultGaps(Gaps),
+    SetSmartGaps(bool),
+    SetGaps(usize, Option<Gaps>),
+    AllocRoot(AreaSize),
+    MoveToRoot(ItemIdx, usize),
+    DestroyRoot(usize, Option<usize>),
+    Navigate(ItemIdx, Direction),
+    Restore(Snapshot),
+    /// Export a root through JSON, and import it as a new one.
+    Import(usize),
+}
+
+fn items(layout: &TestLayout) -> impl Iterator<Item = ItemIdx> + '_ {
+    layout
+        .windows
+        .keys()
+        .map(|&w_idx| ItemIdx::Window(w_idx))
+        .chain(
+            layout
+                .containers
+                .keys()
+                .map(|&c_idx| ItemIdx::Container(c_idx)),
+        )
+}
+
+fn random_size(rng: &mut StdRng) -> AreaSize {
+    // Mostly screen-sized, but sometimes tiny, to exercise saturation.
+    let max = if rng.gen_bool(0.1) { 20 } else { 3000 };
+    AreaSize {
+        width: rng.gen_range(0..max),
+        height: rng.gen_range(0..max),
+    }
+}
+
+fn random_padding(rng: &mut StdRng) -> Padding {
+    Padding {
+        left: rng.gen_range(0..4),
+        right: rng.gen_range(0..4),
+        top: rng.gen_range(0..4),
+        bottom: rng.gen_range(0..4),
+    }
+}
+
+fn random_gaps(rng: &mut StdRng) -> Gaps {
+    Gaps {
+        inner: rng.gen_range(0..4),
+        outer: rng.gen_range(0..4),
+    }
+}
+
+fn random_direction(rng: &mut StdRng) -> Direction {
+    *[
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ]
+    .choose(rng)
+    .unwrap()
+}
+
+/// A random valid cursor that isn't inside `moving`.
+fn random_cursor(
+    layout: &TestLayout,
+    moving: Option<ItemIdx>,
+    rng: &mut StdRng,
+) -> Option<MoveCursor> {
+    let target = items(layout)
+        .filter(|&item| layout.root_of(item).is_some())
+        .filter(|&item| !moving.is_some_and(|moving| layout.is_ancestor(moving, item)))
+        .choose(rng)?;
+    match target {
+        ItemIdx::Container(container) if rng.gen_bool(0.5) => Some(MoveCursor::Into {
+            container,
+            index: rng.gen_range(0..=layout.n_children(target)),
+        }),
+        item => Some(MoveCursor::Split {
+            item,
+            direction: random_direction(rng),
+        }),
+    }
+}
+
+fn random_op(layout: &TestLayout, snapshots: &[Snapshot], rng: &mut StdRng) -> Op {
+    let roots = layout.roots().collect::<Vec<_>>();
+    let placed = items(layout)
+        .filter(|&item| layout.parent_container(item).is_some())
+        .collect::<Vec<_>>();
+    loop {
+        let op = match rng.gen_range(0..12) {
+            0..=2 => random_cursor(layout, None, rng).map(Op::Insert),
+            3..=4 => placed.choose(rng).and_then(|&item| {
+                random_cursor(layout, Some(item), rng).map(|cursor| Op::Move(item, cursor))
+            }),
+            5 => items(layout).choose(rng).map(Op::Destroy),
+            6 => roots.choose(rng).map(|&root_ctr| {
+                let content = random_size(rng);
+                Op::Resize(WindowBounds {
+                    content,
+                    position: Position {
+                        x: rng.gen_range(0..100),
+                        y: rng.gen_range(0..100),
+                        root_ctr,
+                    },
+                })
+            }),
+            7 => placed
+                .choose(rng)
+                .map(|&item| Op::SetContentLength(item, rng.gen_range(0..3000))),
+            8 => match rng.gen_range(0..4) {
+                0 => Some(Op::AllocRoot(random_size(rng))),
+                1 => {
+                    let root = *roots.choose(rng).unwrap();
+                    let rehome = roots
+                        .iter()
+                        .copied()
+                        .filter(|&other| other != root)
+                        .choose(rng);
+                    // Keep at least one root around.
+                    (roots.len() > 1).then(|| Op::DestroyRoot(root, rehome.filter(|_| rng.gen())))
+                }
+                _ => placed
+                    .choose(rng)
+                    .map(|&item| Op::MoveToRoot(item, *roots.choose(rng).unwrap())),
+            },
+            9 if rng.gen_bool(0.1) => Some(Op::SetDefaultPadding(random_padding(rng))),
+            9 if rng.gen_bool(0.1) => layout.containers.keys().choose(rng).map(|&c_idx| {
+                Op::SetPadding(c_idx, rng.gen_bool(0.8).then(|| random_padding(rng)))
+            }),
+            9 if rng.gen_bool(0.1) => roots
+                .choose(rng)
+                .map(|&root| Op::SetRootMargins(root, random_padding(rng))),
+            9 if rng.gen_bool(0.1) => Some(Op::SetDefaultGaps(random_gaps(rng))),
+            9 if rng.gen_bool(0.1) => Some(Op::SetSmartGaps(rng.gen())),
+            9 if rng.gen_bool(0.1) => layout
+                .containers
+                .keys()
+                .choose(rng)
+                .map(|&c_idx| Op::SetGaps(c_idx, rng.gen_bool(0.8).then(|| random_gaps(rng)))),
+            9 if rng.gen_bool(0.1) => roots.choose(rng).map(|&root| Op::Import(root)),
+            9 => layout
+                .containers
+                .keys()
+                .choose(rng)
+                .map(|&c_idx| Op::Equalize(c_idx)),
+            10 => placed
+                .choose(rng)
+                .map(|&item| Op::Navigate(item, random_direction(rng))),
+            _ => snapshots
+                .iter()
+                .filter(|snapshot| roots.contains(&snapshot.root()))
+                .choose(rng)
+                .map(|snapshot| Op::Restore(snapshot.clone())),
+        };
+        if let Some(op) = op {
+            return op;
+        }
+    }
+}
+
+fn apply(layout: &mut TestLayout, op: &Op) -> Vec<LayoutAction<(), ()>> {
+    match *op {
+        Op::Insert(cursor) => {
+            let root_ctr = layout.bounds(cursor.item()).position.root_ctr;
+            let w_idx = layout.alloc_window((), root_ctr);
+            layout.r#move(ItemIdx::Window(w_idx), cursor)
+        }
+        Op::Move(item, cursor) => layout.r#move(item, cursor),
+        Op::Destroy(item) => layout.destroy(item),
+        Op::Resize(bounds) => layout.resize(bounds),
+        Op::SetContentLength(item, length) => layout.set_content_length(item, length),
+        Op::Equalize(c_idx) => layout.equalize_container_children(c_idx),
+        Op::SetDefaultPadding(padding) => layout.set_default_padding(padding),
+        Op::SetPadding(c_idx, padding) => layout.set_padding(c_idx, padding),
+        Op::SetRootMargins(root, margins) => layout.set_root_margins(root, margins),
+        Op::SetDefaultGaps(gaps) => layout.set_default_gaps(gaps),
+        Op::SetSmartGaps(smart) => layout.set_smart_gaps(smart),
+        Op::SetGaps(c_idx, gaps) => layout.set_gaps(c_idx, gaps),
+        Op::AllocRoot(content) => {
+            let root_ctr = layout.alloc_root();
+            layout.resize(WindowBounds {
+                content,
+                position: Position {
+                    x: 0,
+                    y: 0,
+                    root_ctr,
+                },
+            })
+        }
+        Op::MoveToRoot(item, root) => layout.move_to_root(item, root),
+        Op::DestroyRoot(root, rehome) => layout.destroy_root(root, rehome),
+        Op::Navigate(item, direction) => {
+            if let Some(to) = layout.navigate(item, direction, None) {
+                assert!(layout.exists(to), "navigated to nonexistent {:?}", to);
+            }
+            vec![]
+        }
+        Op::Restore(ref snapshot) => layout.restore(snapshot, |_| ()),
+        Op::Import(root) => {
+            let exported = layout.export(root, |w_idx, _| w_idx);
+            let json = serde_json::to_string(&exported).unwrap();
+            let exported: ExportedRoot<usize> = serde_json::from_str(&json).unwrap();
+            let Imported {
+                root: new_root,
+                windows,
+                actions,
+            } = layout.import(&exported, |_, _| ()).unwrap();
+            let reexported = layout.export(new_root, |w_idx, _| w_idx);
+            assert_eq!(shape(&exported.tree), shape(&reexported.tree));
+            // Each exported window has a new one, recorded by its exported id.
+            let mut ids = vec![];
+            collect_window_ids(&exported.tree, &mut ids);
+            assert_eq!(ids.len(), windows.len());
+            assert!(ids.iter().all(|id| windows.contains_key(id)));
+            actions
+        }
+    }
+}
+
+/// What `Layout::import` should preserve of an exported tree: everything but ids and bounds.
+fn shape(node: &ExportedNode<usize>) -> String {
+    match node {
+        ExportedNode::Window { .. } => "W".to_string(),
+        ExportedNode::Container {
+            strategy, children, ..
+        } => {
+            let children = children
+                .iter()
+                .map(|(weight, child)| format!("{weight}:{}", shape(child)))
+                .collect::<Vec<_>>();
+            format!("{strategy:?}[{}]", children.join(","))
+        }
+    }
+}
+
+fn collect_window_ids(node: &ExportedNode<usize>, ids: &mut Vec<usize>) {
+    match node {
+        ExportedNode::Window { id, .. } => ids.push(*id),
+        ExportedNode::Container { children, .. } => {
+            for (_weight, child) in children {
+                collect_window_ids(child, ids);
+            }
+        }
+    }
+}
+
+/// Check that the children of `c_idx` exactly cover its area,
+/// minus padding and gaps, in order and without overlap.
+fn check_tiling(layout: &TestLayout, c_idx: usize) -> Result<(), String> {
+    let ctr = &layout.containers[&c_idx];
+    let gaps = layout.gaps(c_idx);
+    let inter = gaps.inner;
+    // Added up here rather than taken from `Layout::edges`, so that that's checked too.
+    let mut edges = layout.padding(c_idx);
+    if let Some(info) = layout.roots.get(&c_idx) {
+        edges = edges + info.margins + Padding::uniform(gaps.outer);
+    }
+    let n = ctr.children.len();
+    if n == 0 {
+        return Ok(());
+    }
+    let outer = ctr.bounds;
+    // Sizes along the container's axis and across it, and the padding before and after each.
+    let (main, cross, (main_before, main_after), (cross_before, cross_after)) = match ctr.strategy {
+        LayoutStrategy::Horizontal => (
+            outer.content.width,
+            outer.content.height,
+            (edges.left, edges.right),
+            (edges.top, edges.bottom),
+        ),
+        LayoutStrategy::Vertical => (
+            outer.content.height,
+            outer.content.width,
+            (edges.top, edges.bottom),
+            (edges.left, edges.right),
+        ),
+    };
+    if main < main_before + main_after + inter * (n - 1) || cross < cross_before + cross_after {
+        // Too small to hold its padding; everything inside saturates to zero size.
+        return Ok(());
+    }
+    let mut expected_start = main_before;
+    for (i, &(_weight, child)) in ctr.children.iter().enumerate() {
+        let inner = layout.bounds(child);
+        if inner.position.root_ctr != outer.position.root_ctr {
+            return Err(format!(
+                "child {i} of {c_idx} is in another root: {inner:?}"
+            ));
+        }
+        // Offsets relative to the container; these wrap if the child starts before it.
+        let dx = inner.position.x.wrapping_sub(outer.position.x);
+        let dy = inner.position.y.wrapping_sub(outer.position.y);
+        let (start, len, cross_start, cross_len) = match ctr.strategy {
+            LayoutStrategy::Horizontal => (dx, inner.content.width, dy, inner.content.height),
+            LayoutStrategy::Vertical => (dy, inner.content.height, dx, inner.content.width),
+        };
+        if cross_start != cross_before || cross_len != cross - cross_before - cross_after {
+            return Err(format!(
+                "child {i} of {c_idx} doesn't span it: {inner:?} in {outer:?}"
+            ));
+        }
+        if start != expected_start {
+            return Err(format!(
+                "child {i} of {c_idx} starts at {start}, expected {expected_start}: {inner:?} in {outer:?}"
+            ));
+        }
+        expected_start = start + len + inter;
+    }
+    let end = expected_start - inter;
+    if end != main - main_after {
+        return Err(format!(
+            "children of {c_idx} end at {end}, expected {}: {outer:?}",
+            main - main_after
+        ));
+    }
+    Ok(())
+}
+
+fn check(layout: &TestLayout, actions: &[LayoutAction<(), ()>]) -> Result<(), String> {
+    layout
+        .check_invariants()
+        .map_err(|violation| violation.to_string())?;
+    for &c_idx in layout.containers.keys() {
+        check_tiling(layout, c_idx)?;
+    }
+    // The client code relies on being told about every new position.
+    let mut last_bounds = HashMap::new();
+    for action in actions {
+        match action {
+            LayoutAction::NewBounds { idx, bounds } => {
+                last_bounds.insert(*idx, *bounds);
+            }
+            LayoutAction::ItemDestroyed { item } => {
+                let item = item.item();
+                let recreated_root =
+                    matches!(item, ItemIdx::Container(c_idx) if layout.roots.contains_key(&c_idx));
+                if layout.exists(item) && !recreated_root {
+                    return Err(format!("{item:?} reported destroyed, but still exists"));
+                }
+            }
+            LayoutAction::ItemHidden { .. } => {}
+        }
+    }
+    for (idx, bounds) in last_bounds {
+        if let Some(actual) = layout.try_bounds(idx) {
+            if actual != bounds {
+                return Err(format!(
+                    "last reported bounds of {idx:?} were {bounds:?}, but it's at {actual:?}"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn random_operations_keep_layout_well_formed() {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut layout = TestLayout::new(
+            UnitCtor,
+            Padding::uniform(rng.gen_range(0..4)),
+            random_gaps(&mut rng),
+        );
+        let root = layout.alloc_root();
+        let content = random_size(&mut rng);
+        layout.resize(WindowBounds {
+            content,
+            position: Position {
+                x: 0,
+                y: 0,
+                root_ctr: root,
+            },
+        });
+        let mut snapshots = vec![];
+        for step in 0..STEPS {
+            if rng.gen_bool(0.1) {
+                let root = layout.roots().choose(&mut rng).unwrap();
+                snapshots.push(layout.snapshot(root));
+            }
+            let op = random_op(&layout, &snapshots, &mut rng);
+            let actions = apply(&mut layout, &op);
+            check(&layout, &actions)
+                .unwrap_or_else(|err| panic!("seed {seed}, step {step}, {op:?}: {err}"));
+        }
+    }
+}
+
+/// A layout with one root of the given size, for tests that check exact results.
+fn fixed_layout(width: usize, height: usize, padding: Padding, gaps: Gaps) -> (TestLayout, usize) {
+    let mut layout = TestLayout::new(UnitCtor, padding, gaps);
+    let root = layout.alloc_root();
+    layout.resize(WindowBounds {
+        content: AreaSize { height, width },
+        position: Position {
+            x: 0,
+            y: 0,
+            root_ctr: root,
+        },
+    });
+    (layout, root)
+}
+
+/// Add a window at the end of `c_idx`.
+fn push_window(layout: &mut TestLayout, c_idx: usize) -> (usize, Vec<LayoutAction<(), ()>>) {
+    let root = layout.root_of(ItemIdx::Container(c_idx)).unwrap();
+    let w_idx = layout.alloc_window((), root);
+    let index = layout.n_children(ItemIdx::Container(c_idx));
+    let actions = layout.r#move(
+        ItemIdx::Window(w_idx),
+        MoveCursor::Into {
+            container: c_idx,
+            index,
+        },
+    );
+    (w_idx, actions)
+}
+
+fn touched(actions: &[LayoutAction<(), ()>]) -> Vec<ItemIdx> {
+    actions.iter().map(LayoutAction::item).collect()
+}
+
+#[test]
+fn undo_and_redo_restore_shapes() {
+    let (mut layout, root) = fixed_layout(100, 100, Padding::uniform(0), Gaps::default());
+    let mut history = History::new(2);
+    history.track_roots(&layout);
+    let empty = layout.snapshot(root);
+
+    let (_, actions) = push_window(&mut layout, root);
+    history.record(&layout, touched(&actions));
+    let one = layout.snapshot(root);
+    let (_, actions) = push_window(&mut layout, root);
+    history.record(&layout, touched(&actions));
+    let two = layout.snapshot(root);
+    assert_eq!(history.depth(root), (2, 0));
+
+    let snapshot = history.step(root, false).unwrap();
+    assert_eq!(snapshot, one);
+    let actions = layout.restore(&snapshot, |_| ());
+    history.record(&layout, touched(&actions));
+    assert_eq!(layout.snapshot(root), one);
+    assert_eq!(history.depth(root), (1, 1));
+
+    let snapshot = history.step(root, true).unwrap();
+    assert_eq!(snapshot, two);
+    let actions = layout.restore(&snapshot, |_| ());
+    history.record(&layout, touched(&actions));
+    assert_eq!(layout.snapshot(root), two);
+    assert_eq!(history.depth(root), (2, 0));
+    assert_eq!(history.step(root, true), None);
+
+    // A new change after an undo drops what could have been redone.
+    let snapshot = history.step(root, false).unwrap();
+    let actions = layout.restore(&snapshot, |_| ());
+    history.record(&layout, touched(&actions));
+    let (_, actions) = push_window(&mut layout, root);
+    history.record(&layout, touched(&actions));
+    assert_eq!(history.depth(root), (2, 0));
+
+    // Only `limit` changes are kept, so the empty root is forgotten.
+    let (_, actions) = push_window(&mut layout, root);
+    history.record(&layout, touched(&actions));
+    assert_eq!(history.depth(root), (2, 0));
+    while let Some(snapshot) = history.step(root, false) {
+        let actions = layout.restore(&snapshot, |_| ());
+        history.record(&layout, touched(&actions));
+    }
+    assert_eq!(layout.snapshot(root), one);
+    assert_ne!(layout.snapshot(root), empty);
+}
+
+#[test]
+fn history_records_only_touched_roots() {
+    let (mut layout, a) = fixed_layout(100, 100, Padding::uniform(0), Gaps::default());
+    let b = layout.alloc_root();
+    layout.resize(WindowBounds {
+        content: AreaSize {
+            height: 100,
+            width: 100,
+        },
+        position: Position {
+            x: 0,
+            y: 0,
+            root_ctr: b,
+        },
+    });
+    let (w1, _) = push_window(&mut layout, a);
+    let (w2, _) = push_window(&mut layout, a);
+    let mut history = History::new(8);
+    history.track_roots(&layout);
+
+    // A change within `b` leaves `a` alone.
+    let (_, actions) = push_window(&mut layout, b);
+    history.record(&layout, touched(&actions));
+    assert_eq!(history.depth(a), (0, 0));
+    assert_eq!(history.depth(b), (1, 0));
+
+    // A move is a change to both roots.
+    let actions = layout.move_to_root(ItemIdx::Window(w1), b);
+    history.record(&layout, touched(&actions));
+    assert_eq!(history.depth(a), (1, 0));
+    assert_eq!(history.depth(b), (2, 0));
+
+    // Even when nothing is left in `a` to lay out again.
+    let before = layout.snapshot(a);
+    let actions = layout.move_to_root(ItemIdx::Window(w2), b);
+    assert!(touched(&actions)
+        .iter()
+        .all(|&item| layout.root_of(item) == Some(b)));
+    history.record(&layout, touched(&actions));
+    assert_eq!(history.depth(a), (2, 0));
+
+    let snapshot = history.step(a, false).unwrap();
+    assert_eq!(snapshot, before);
+}
+
+#[test]
+fn undoing_a_destroyed_slot_can_take_back_its_client() {
+    // Windows carry the client in them, as the window manager's do.
+    let mut layout =
+        Layout::<Option<u32>, (), UnitCtor>::new(UnitCtor, Padding::uniform(0), Gaps::default());
+    let root = layout.alloc_root();
+    layout.resize(bounds_at(root, 0, 0, 90, 30));
+    let mut history = History::new(4);
+    history.track_roots(&layout);
+    let slots = (0..3)
+        .map(|client| {
+            let w_idx = layout.alloc_window(Some(client), root);
+            let index = layout.n_children(ItemIdx::Container(root));
+            let actions = layout.r#move(
+                ItemIdx::Window(w_idx),
+                MoveCursor::Into {
+                    container: root,
+                    index,
+                },
+            );
+            history.record(&layout, actions.iter().map(LayoutAction::item));
+            w_idx
+        })
+        .collect::<Vec<_>>();
+    let killed = ItemIdx::Window(slots[1]);
+    let bounds = layout.bounds(killed);
+
+    // Remember which client each destroyed slot held, as `WmState` does for its orphans.
+    let actions = layout.destroy(killed);
+    history.record(&layout, actions.iter().map(LayoutAction::item));
+    let mut orphaned = HashMap::new();
+    for action in actions {
+        if let LayoutAction::ItemDestroyed {
+            item: ItemAndData::Window(w_idx, Some(client)),
+        } = action
+        {
+            orphaned.insert(w_idx, client);
+        }
+    }
+    assert_eq!(orphaned, HashMap::from([(slots[1], 1)]));
+
+    let snapshot = history.step(root, false).unwrap();
+    layout.restore(&snapshot, |w_idx| orphaned.remove(&w_idx));
+    assert!(orphaned.is_empty());
+    assert_eq!(layout.try_window_data(slots[1]), Some(&Some(1)));
+    assert_eq!(layout.bounds(killed), bounds);
+    assert_eq!(
+        layout
+            .children(root)
+            .iter()
+            .map(|&(_weight, child)| child)
+            .collect::<Vec<_>>(),
+        slots
+            .iter()
+            .map(|&w_idx| ItemIdx::Window(w_idx))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn checked_methods_report_each_error() {
+    let (mut layout, a) = fixed_layout(100, 100, Padding::uniform(0), Gaps::default());
+    let b = layout.alloc_root();
+    let (w1, _) = push_window(&mut layout, a);
+    let (w2, _) = push_window(&mut layout, a);
+    let (w1, w2) = (ItemIdx::Window(w1), ItemIdx::Window(w2));
+    layout.r#move(
+        w2,
+        MoveCursor::Split {
+            item: w1,
+            direction: Direction::Down,
+        },
+    );
+    let split = layout.parent_container(w1).unwrap();
+    assert_ne!(split, a);
+
+    let missing = ItemIdx::Window(100);
+    assert_eq!(
+        layout.check_item(missing),
+        Err(LayoutError::NoSuchItem(missing))
+    );
+    assert_eq!(layout.check_root(split), Err(LayoutError::NotARoot(split)));
+
+    // Roots can't be moved, even somewhere outside themselves.
+    let into_b = MoveCursor::Into {
+        container: b,
+        index: 0,
+    };
+    assert_eq!(
+        layout.checked_move(ItemIdx::Container(a), into_b).err(),
+        Some(LayoutError::IsARoot(a))
+    );
+    assert_eq!(
+        layout.checked_move_to_root(ItemIdx::Container(a), b).err(),
+        Some(LayoutError::IsARoot(a))
+    );
+
+    let out_of_range = MoveCursor::Into {
+        container: a,
+        index: 5,
+    };
+    assert_eq!(
+        layout.checked_move(w1, out_of_range).err(),
+        Some(LayoutError::InvalidCursor(out_of_range))
+    );
+    let inside = MoveCursor::Split {
+        item: w1,
+        direction: Direction::Left,
+    };
+    assert_eq!(
+        layout.checked_move(ItemIdx::Container(split), inside).err(),
+        Some(LayoutError::MoveIntoSelf {
+            item: ItemIdx::Container(split),
+            cursor: inside,
+        })
+    );
+    let past_end = ChildLocation {
+        container: a,
+        index: 2,
+    };
+    assert_eq!(
+        layout.checked_item_from_child_location(past_end),
+        Err(LayoutError::NoSuchChild(past_end))
+    );
+
+    let mut exported = layout.export(a, |w_idx, _| w_idx);
+    exported.version = EXPORT_VERSION + 1;
+    assert!(matches!(
+        layout.import(&exported, |_, _| ()),
+        Err(LayoutError::UnsupportedExportVersion(version)) if version == EXPORT_VERSION + 1
+    ));
+    exported.version = EXPORT_VERSION;
+    let ExportedNode::Container { children, .. } = &mut exported.tree else {
+        unreachable!()
+    };
+    children[0].0 = -1.0;
+    assert!(matches!(
+        layout.import(&exported, |_, _| ()),
+        Err(LayoutError::BadExport(ItemIdx::Container(root))) if root == exported.root
+    ));
+
+    // A deserialized layout can't make containers.
+    let json = serde_json::to_string(&layout).unwrap();
+    let mut layout: TestLayout = serde_json::from_str(&json).unwrap();
+    assert_eq!(layout.checked_alloc_root(), Err(LayoutError::NoConstructor));
+    let split_w1 = MoveCursor::Split {
+        item: w1,
+        direction: Direction::Up,
+    };
+    assert_eq!(
+        layout.checked_move(w2, split_w1).err(),
+        Some(LayoutError::NoConstructor)
+    );
+    let exported = layout.export(a, |w_idx, _| w_idx);
+    assert!(matches!(
+        layout.import(&exported, |_, _| ()),
+        Err(LayoutError::NoConstructor)
+    ));
+}
+
+/// An export as another program might write it, in the form documented
+/// on `ExportedRoot`, with each window's title as its data.
+const EXPORTED_JSON: &str = r#"{
+  "version": 1,
+  "root": 42,
+  "name": "1",
+  "point": {"Window": 123},
+  "cursor": null,
+  "tree": {"Container": {
+    "id": 42,
+    "strategy": "Horizontal",
+    "bounds": {"content": {"height": 50, "width": 100},
+               "position": {"x": 0, "y": 0, "root_ctr": 42}},
+    "children": [
+      [1.0, {"Window": {
+        "id": 123,
+        "bounds": {"content": {"height": 50, "width": 50},
+                   "position": {"x": 0, "y": 0, "root_ctr": 42}},
+        "data": "xterm"}}],
+      [3.0, {"Window": {
+        "id": 124,
+        "bounds": {"content": {"height": 50, "width": 50},
+                   "position": {"x": 50, "y": 0, "root_ctr": 42}},
+        "data": null}}]
+    ]}}
+}"#;
+
+#[test]
+fn imports_fixed_json() {
+    let (mut layout, _) = fixed_layout(10, 10, Padding::uniform(0), Gaps::default());
+    let exported: ExportedRoot<Option<String>> = serde_json::from_str(EXPORTED_JSON).unwrap();
+    let mut titles = vec![];
+    let Imported { root, windows, .. } = layout
+        .import(&exported, |id, title| titles.push((id, title.clone())))
+        .unwrap();
+    assert_eq!(titles, [(123, Some("xterm".to_string())), (124, None)]);
+    assert_eq!(windows.keys().copied().collect::<Vec<_>>(), [123, 124]);
+    assert_eq!(layout.root_name(root), Some("1"));
+    // Sized like the exported root, and split by the exported weights.
+    let bounds = |id| layout.bounds(ItemIdx::Window(windows[&id]));
+    let at = |x, width| WindowBounds {
+        content: AreaSize { height: 50, width },
+        position: Position {
+            x,
+            y: 0,
+            root_ctr: root,
+        },
+    };
+    assert_eq!(bounds(123), at(0, 25));
+    assert_eq!(bounds(124), at(25, 75));
+
+    let duplicated = EXPORTED_JSON.replace(r#""id": 124"#, r#""id": 123"#);
+    let exported: ExportedRoot<Option<String>> = serde_json::from_str(&duplicated).unwrap();
+    let n_roots = layout.roots().count();
+    assert!(matches!(
+        layout.import(&exported, |_, _| ()),
+        Err(LayoutError::BadExport(ItemIdx::Window(123)))
+    ));
+    assert_eq!(layout.roots().count(), n_roots);
+}
+
+#[test]
+fn layout_errors_read_as_sentences() {
+    assert_eq!(
+        LayoutError::NoSuchItem(ItemIdx::Window(3)).to_string(),
+        "window 3 doesn't exist"
+    );
+    assert_eq!(
+        LayoutError::NoSuchChild(ChildLocation {
+            container: 1,
+            index: 4
+        })
+        .to_string(),
+        "container 1 has no child at index 4"
+    );
+    assert_eq!(
+        InvariantViolation::DuplicateChild {
+            item: ItemIdx::Container(2)
+        }
+        .to_string(),
+        "container 2 is listed as a child more than once"
+    );
+}
+
+/// `WindowBounds` in `root`, for comparing with exact expected positions.
+fn bounds_at(root: usize, x: usize, y: usize, width: usize, height: usize) -> WindowBounds {
+    WindowBounds {
+        content: AreaSize { height, width },
+        position: Position {
+            x,
+            y,
+            root_ctr: root,
+        },
+    }
+}
+
+#[test]
+fn smart_gaps_only_apply_to_single_children() {
+    let gaps = Gaps {
+        inner: 10,
+        outer: 5,
+    };
+    let (mut layout, root) = fixed_layout(100, 50, Padding::uniform(0), gaps);
+    let (w1, _) = push_window(&mut layout, root);
+    let w1 = ItemIdx::Window(w1);
+    assert_eq!(layout.bounds(w1), bounds_at(root, 5, 5, 90, 40));
+    layout.set_smart_gaps(true);
+    assert_eq!(layout.gaps(root), Gaps::default());
+    assert_eq!(layout.bounds(w1), bounds_at(root, 0, 0, 100, 50));
+
+    let (w2, _) = push_window(&mut layout, root);
+    let w2 = ItemIdx::Window(w2);
+    for smart in [true, false] {
+        layout.set_smart_gaps(smart);
+        assert_eq!(layout.gaps(root), gaps);
+        assert_eq!(layout.bounds(w1), bounds_at(root, 5, 5, 40, 40));
+        assert_eq!(layout.bounds(w2), bounds_at(root, 55, 5, 40, 40));
+    }
+}
+
+#[test]
+fn only_roots_have_outer_gaps() {
+    let gaps = Gaps {
+        inner: 10,
+        outer: 5,
+    };
+    let (mut layout, root) = fixed_layout(100, 50, Padding::uniform(0), gaps);
+    let (w1, _) = push_window(&mut layout, root);
+    let (w2, _) = push_window(&mut layout, root);
+    let (w3, _) = push_window(&mut layout, root);
+    let [w1, w2, w3] = [w1, w2, w3].map(ItemIdx::Window);
+    layout.r#move(
+        w3,
+        MoveCursor::Split {
+            item: w2,
+            direction: Direction::Down,
+        },
+    );
+    let nested = layout.parent_container(w2).unwrap();
+    assert_eq!(
+        layout.gaps(nested),
+        Gaps {
+            inner: 10,
+            outer: 0
+        }
+    );
+    assert_eq!(layout.bounds(w1), bounds_at(root, 5, 5, 40, 40));
+    assert_eq!(
+        layout.bounds(ItemIdx::Container(nested)),
+        bounds_at(root, 55, 5, 40, 40)
+    );
+    assert_eq!(layout.bounds(w2), bounds_at(root, 55, 5, 40, 15));
+    assert_eq!(layout.bounds(w3), bounds_at(root, 55, 30, 40, 15));
+
+    // Even with its own gaps, a nested container has no outer one.
+    layout.set_gaps(
+        nested,
+        Some(Gaps {
+            inner: 0,
+            outer: 20,
+        }),
+    );
+    assert_eq!(
+        layout.own_gaps(nested),
+        Some(Gaps {
+            inner: 0,
+            outer: 20
+        })
+    );
+    assert_eq!(layout.bounds(w2), bounds_at(root, 55, 5, 40, 20));
+    assert_eq!(layout.bounds(w3), bounds_at(root, 55, 25, 40, 20));
+}
+
+#[test]
+fn padding_margins_and_outer_gap_add_up() {
+    let padding = Padding {
+        left: 1,
+        right: 2,
+        top: 3,
+        bottom: 4,
+    };
+    let gaps = Gaps { inner: 6, outer: 4 };
+    let (mut layout, root) = fixed_layout(201, 100, padding, gaps);
+    layout.set_root_margins(
+        root,
+        Padding {
+            left: 10,
+            right: 20,
+            top: 5,
+            bottom: 15,
+        },
+    );
+    let (w1, _) = push_window(&mut layout, root);
+    let (w2, _) = push_window(&mut layout, root);
+    let (w3, _) = push_window(&mut layout, root);
+    let [w1, w2, w3] = [w1, w2, w3].map(ItemIdx::Window);
+    layout.r#move(
+        w3,
+        MoveCursor::Split {
+            item: w2,
+            direction: Direction::Down,
+        },
+    );
+    let nested = layout.parent_container(w2).unwrap();
+    // The root's children start 1 + 10 + 4 from the left and 3 + 5 + 4 from
+    // the top, and stop 2 + 20 + 4 from the right and 4 + 15 + 4 from the bottom.
+    assert_eq!(layout.bounds(w1), bounds_at(root, 15, 12, 77, 65));
+    assert_eq!(
+        layout.bounds(ItemIdx::Container(nested)),
+        bounds_at(root, 98, 12, 77, 65)
+    );
+    // A nested container only has its padding.
+    assert_eq!(layout.bounds(w2), bounds_at(root, 99, 15, 74, 26));
+    assert_eq!(layout.bounds(w3), bounds_at(root, 99, 47, 74, 26));
+}
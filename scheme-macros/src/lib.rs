@@ -0,0 +1,273 @@
+//! The `#[scheme_fn]` attribute, re-exported and documented by btv-scheme.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, FnArg, GenericArgument, ItemFn, LitByteStr, LitStr,
+    PathArguments, ReturnType, Type,
+};
+
+/// The most arguments `scm_c_define_gsubr` accepts.
+const MAX_ARGS: usize = 10;
+
+/// How an argument or result crosses between Rust and Scheme.
+enum Conversion {
+    /// A raw `SCM`, passed through untouched.
+    Scm,
+    /// `&T` or `&mut T`, where `T` lives in a foreign object.
+    Foreign { elem: Type, mutable: bool },
+    /// Anything else, converted by serde.
+    Serde(Type),
+}
+
+impl Conversion {
+    fn of(ty: &Type) -> Self {
+        match ty {
+            Type::Reference(r) => Conversion::Foreign {
+                elem: (*r.elem).clone(),
+                mutable: r.mutability.is_some(),
+            },
+            _ if matches!(last_segment(ty), Some((name, _)) if name == "SCM") => Conversion::Scm,
+            _ => Conversion::Serde(ty.clone()),
+        }
+    }
+
+    /// An expression converting the `SCM` in `arg` to the parameter's type.
+    /// Foreign objects are borrowed beforehand, by [`Conversion::borrow`].
+    fn convert_arg(&self, arg: &syn::Ident, error: &Type) -> TokenStream2 {
+        match self {
+            Conversion::Scm => quote!(#arg),
+            Conversion::Foreign { mutable: true, .. } => quote!(&mut *#arg),
+            Conversion::Foreign { mutable: false, .. } => quote!(&*#arg),
+            Conversion::Serde(ty) => quote! {
+                ::btv_scheme::__private::from_scm::<#ty>(
+                    #arg,
+                    <#error as ::btv_scheme::SchemeFnError>::CONFIG,
+                )?
+            },
+        }
+    }
+
+    /// A statement shadowing `arg` with a borrow of its foreign object,
+    /// held until the function returns.
+    fn borrow(&self, arg: &syn::Ident) -> Option<TokenStream2> {
+        match self {
+            Conversion::Foreign { elem, mutable } => {
+                let mutability = mutable.then(|| quote!(mut));
+                Some(quote! {
+                    let #mutability #arg = ::btv_scheme::__private::foreign_ref::<#elem>(#arg)?;
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The last segment of a path type, with its generic arguments.
+fn last_segment(ty: &Type) -> Option<(String, &PathArguments)> {
+    match ty {
+        Type::Path(p) if p.qself.is_none() => p
+            .path
+            .segments
+            .last()
+            .map(|seg| (seg.ident.to_string(), &seg.arguments)),
+        _ => None,
+    }
+}
+
+/// The type inside `Option<..>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    match last_segment(ty)? {
+        (name, PathArguments::AngleBracketed(args)) if name == "Option" && args.args.len() == 1 => {
+            match &args.args[0] {
+                GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The `T` and `E` of `Result<T, E>`.
+fn result_types(ty: &Type) -> Option<(&Type, &Type)> {
+    match last_segment(ty)? {
+        (name, PathArguments::AngleBracketed(args)) if name == "Result" && args.args.len() == 2 => {
+            match (&args.args[0], &args.args[1]) {
+                (GenericArgument::Type(ok), GenericArgument::Type(err)) => Some((ok, err)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(t) if t.elems.is_empty())
+}
+
+/// See the docs on the re-export in btv-scheme.
+#[proc_macro_attribute]
+pub fn scheme_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(attr as LitStr);
+    let mut fun = parse_macro_input!(item as ItemFn);
+    match expand(&name, &mut fun) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => {
+            let e = e.to_compile_error();
+            quote!(#fun #e).into()
+        }
+    }
+}
+
+fn expand(name: &LitStr, fun: &mut ItemFn) -> syn::Result<TokenStream2> {
+    let name_value = name.value();
+    if name_value.contains('\0') {
+        return Err(syn::Error::new(
+            name.span(),
+            "Scheme names can't contain nul",
+        ));
+    }
+    let c_name = LitByteStr::new(format!("{}\0", name_value).as_bytes(), name.span());
+    if !fun.sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            fun.sig.generics.span(),
+            "scheme_fn functions can't be generic",
+        ));
+    }
+    if fun.sig.inputs.len() > MAX_ARGS {
+        return Err(syn::Error::new(
+            fun.sig.inputs.span(),
+            format!("Scheme procedures can take at most {} arguments", MAX_ARGS),
+        ));
+    }
+
+    let (ok_ty, error_ty, fallible) = match &fun.sig.output {
+        ReturnType::Default => (None, syn::parse_quote!(::btv_scheme::Error), false),
+        ReturnType::Type(_, ty) => match result_types(ty) {
+            Some((ok, err)) => (Some(ok.clone()), err.clone(), true),
+            None => (
+                Some((**ty).clone()),
+                syn::parse_quote!(::btv_scheme::Error),
+                false,
+            ),
+        },
+    };
+
+    let mut params = vec![];
+    let mut borrows = vec![];
+    let mut args = vec![];
+    let mut required = 0i32;
+    let mut optional = 0i32;
+    for (i, input) in fun.sig.inputs.iter_mut().enumerate() {
+        let pat = match input {
+            FnArg::Receiver(r) => {
+                return Err(syn::Error::new(
+                    r.span(),
+                    "scheme_fn functions can't take self",
+                ))
+            }
+            FnArg::Typed(pat) => pat,
+        };
+        let n_attrs = pat.attrs.len();
+        pat.attrs.retain(|attr| !attr.path.is_ident("optional"));
+        let is_optional = pat.attrs.len() < n_attrs;
+
+        let param = format_ident!("arg{}", i);
+        let arg = if is_optional {
+            let inner = option_inner(&pat.ty).ok_or_else(|| {
+                syn::Error::new(pat.ty.span(), "#[optional] parameters must be `Option`s")
+            })?;
+            optional += 1;
+            let conversion = Conversion::of(inner);
+            if let Conversion::Foreign { .. } = conversion {
+                return Err(syn::Error::new(
+                    inner.span(),
+                    "#[optional] parameters can't be references",
+                ));
+            }
+            let some = conversion.convert_arg(&param, &error_ty);
+            quote! {
+                if #param == ::btv_scheme::__private::SCM_UNDEFINED {
+                    ::std::option::Option::None
+                } else {
+                    ::std::option::Option::Some(#some)
+                }
+            }
+        } else {
+            if optional > 0 {
+                return Err(syn::Error::new(
+                    pat.span(),
+                    "#[optional] parameters must come last",
+                ));
+            }
+            required += 1;
+            let conversion = Conversion::of(&pat.ty);
+            borrows.extend(conversion.borrow(&param));
+            conversion.convert_arg(&param, &error_ty)
+        };
+        params.push(quote!(#param: ::btv_scheme::__private::SCM));
+        args.push(arg);
+    }
+
+    let ident = &fun.sig.ident;
+    let invoke = quote!(#ident(#(#args),*));
+    let call = if fallible {
+        quote!(#invoke?)
+    } else {
+        invoke.clone()
+    };
+    let result = match ok_ty.as_ref().map(|ty| (ty, Conversion::of(ty))) {
+        None => quote! {
+            #call;
+            ::std::result::Result::Ok(::btv_scheme::__private::SCM_UNSPECIFIED)
+        },
+        Some((ty, _)) if is_unit(ty) => quote! {
+            #call;
+            ::std::result::Result::Ok(::btv_scheme::__private::SCM_UNSPECIFIED)
+        },
+        Some((_, Conversion::Scm)) if fallible => invoke,
+        Some((_, Conversion::Scm)) => quote!(::std::result::Result::Ok(#invoke)),
+        Some((ty, Conversion::Foreign { .. })) => {
+            return Err(syn::Error::new(
+                ty.span(),
+                "scheme_fn functions can't return references",
+            ))
+        }
+        Some((_, Conversion::Serde(_))) => quote! {
+            let value = #call;
+            ::std::result::Result::Ok(::btv_scheme::__private::to_scm(
+                &value,
+                <#error_ty as ::btv_scheme::SchemeFnError>::CONFIG,
+            )?)
+        },
+    };
+
+    let subr = format_ident!("__scheme_fn_{}", ident);
+    Ok(quote! {
+        #fun
+
+        const _: () = {
+            #[allow(non_snake_case, clippy::needless_question_mark)]
+            unsafe extern "C" fn #subr(#(#params),*) -> ::btv_scheme::__private::SCM {
+                ::btv_scheme::guard(
+                    ::std::ffi::CStr::from_bytes_with_nul_unchecked(#c_name),
+                    || -> ::std::result::Result<::btv_scheme::__private::SCM, #error_ty> {
+                        #(#borrows)*
+                        #result
+                    },
+                )
+            }
+
+            ::btv_scheme::__private::inventory::submit! {
+                ::btv_scheme::SchemeFn::__new(
+                    // Checked for nul bytes above.
+                    unsafe { ::std::ffi::CStr::from_bytes_with_nul_unchecked(#c_name) },
+                    #required,
+                    #optional,
+                    #subr as *mut ::std::ffi::c_void,
+                )
+            }
+        };
+    })
+}
@@ -0,0 +1,268 @@
+//! Rust values in Guile foreign objects, and a serde bridge for them.
+
+use std::{
+    cell::Cell,
+    ffi::{c_void, CStr},
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    panic::AssertUnwindSafe,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, Ordering},
+        OnceLock,
+    },
+    thread::{self, ThreadId},
+};
+
+use rust_guile::{
+    scm_class_of, scm_dynwind_begin, scm_dynwind_end, scm_dynwind_unwind_handler,
+    scm_foreign_object_ref, scm_from_utf8_symbol, scm_gc_protect_object, scm_gc_unprotect_object,
+    scm_list_1, scm_make_foreign_object_1, scm_make_foreign_object_type, scm_t_wind_flags, SCM,
+};
+use serde::{de, Deserialize, Serialize};
+
+use crate::{Error, Rooted};
+
+const SCM_F_WIND_EXPLICITLY: scm_t_wind_flags = 1 << 4;
+
+/// What a foreign object's only slot points to.
+struct Slot<T> {
+    /// Null once a lent value has been given back.
+    value: AtomicPtr<T>,
+    /// Whether `value` was boxed by [`ForeignType::make`], and is dropped with the object.
+    owned: bool,
+    /// The thread that lent the value, which is the only one that may use it.
+    lender: Option<ThreadId>,
+    borrowed: AtomicBool,
+}
+
+/// A Guile foreign object type whose objects hold `T`s. It's usually kept
+/// in a `static`, and made in Guile the first time it's needed.
+///
+/// An object either owns its value, which is dropped when the object is
+/// garbage collected, or borrows it for the length of a call with
+/// [`lend`](Self::lend). Either way, Rust code gets at the value with
+/// [`borrow_mut`](Self::borrow_mut), which fails rather than alias a value
+/// that's already borrowed.
+pub struct ForeignType<T> {
+    name: &'static CStr,
+    scm_type: OnceLock<Rooted>,
+    marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T: 'static> ForeignType<T> {
+    pub const fn new(name: &'static CStr) -> Self {
+        ForeignType {
+            name,
+            scm_type: OnceLock::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn scm_type(&self) -> SCM {
+        self.scm_type
+            .get_or_init(|| unsafe {
+                let name = scm_from_utf8_symbol(self.name.as_ptr());
+                let slots = scm_list_1(scm_from_utf8_symbol(c"data".as_ptr()));
+                Rooted::new(scm_make_foreign_object_type(
+                    name,
+                    slots,
+                    Some(finalize::<T>),
+                ))
+            })
+            .get()
+    }
+
+    /// Whether `scm` is one of this type's objects.
+    // An `SCM` is a Guile value rather than a pointer, and any value has a class.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn is_instance(&self, scm: SCM) -> bool {
+        unsafe { scm_class_of(scm) == self.scm_type() }
+    }
+
+    /// Exclusive access to the value in `scm`, which must be one of this
+    /// type's objects. The object must stay reachable while it's borrowed,
+    /// which it does as long as the `ForeignRef` stays on the stack.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn borrow_mut(&self, scm: SCM) -> Result<ForeignRef<'_, T>, Error> {
+        if !self.is_instance(scm) {
+            return Err(Error::ExpectedForeign {
+                type_name: self.name.to_string_lossy().into_owned(),
+            });
+        }
+        let slot = unsafe { &*(scm_foreign_object_ref(scm, 0) as *const Slot<T>) };
+        if slot
+            .lender
+            .is_some_and(|lender| lender != thread::current().id())
+        {
+            return Err(Error::ForeignWrongThread);
+        }
+        if slot.value.load(Ordering::Acquire).is_null() {
+            return Err(Error::ForeignExpired);
+        }
+        if slot.borrowed.swap(true, Ordering::Acquire) {
+            return Err(Error::ForeignBorrowed);
+        }
+        Ok(ForeignRef { slot, _object: scm })
+    }
+
+    /// Call `f` with a new object that borrows `value`. Once `f` returns,
+    /// or Guile unwinds out of it, the object is empty, and borrowing from it
+    /// fails with [`Error::ForeignExpired`].
+    pub fn lend<R>(&self, value: &mut T, f: impl FnOnce(SCM) -> R) -> R {
+        let slot = Box::new(Slot {
+            value: AtomicPtr::new(value),
+            owned: false,
+            lender: Some(thread::current().id()),
+            borrowed: AtomicBool::new(false),
+        });
+        unsafe {
+            let scm =
+                scm_make_foreign_object_1(self.scm_type(), Box::into_raw(slot) as *mut c_void);
+            // Unprotected by `expire`.
+            scm_gc_protect_object(scm);
+            scm_dynwind_begin(0);
+            scm_dynwind_unwind_handler(
+                Some(expire::<T>),
+                scm as *mut c_void,
+                SCM_F_WIND_EXPLICITLY,
+            );
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(scm)));
+            scm_dynwind_end();
+            result.unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+        }
+    }
+}
+
+impl<T: Send + 'static> ForeignType<T> {
+    /// A new object owning `value`.
+    pub fn make(&self, value: T) -> SCM {
+        let slot = Box::new(Slot {
+            value: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            owned: true,
+            lender: None,
+            borrowed: AtomicBool::new(false),
+        });
+        unsafe { scm_make_foreign_object_1(self.scm_type(), Box::into_raw(slot) as *mut c_void) }
+    }
+}
+
+impl<T: Clone + 'static> ForeignType<T> {
+    /// A copy of the value in `scm`.
+    pub fn get(&self, scm: SCM) -> Result<T, Error> {
+        Ok(self.borrow_mut(scm)?.clone())
+    }
+}
+
+/// Run by Guile, possibly on its finalizer thread, once `obj` is unreachable.
+/// Only objects made by `make`, whose values are `Send`, own their values.
+unsafe extern "C" fn finalize<T>(obj: SCM) {
+    let slot = Box::from_raw(scm_foreign_object_ref(obj, 0) as *mut Slot<T>);
+    if slot.owned {
+        drop(Box::from_raw(slot.value.load(Ordering::Relaxed)));
+    }
+}
+
+/// Give back the value that `obj` was lent.
+unsafe extern "C" fn expire<T>(obj: *mut c_void) {
+    let obj = obj as SCM;
+    let slot = &*(scm_foreign_object_ref(obj, 0) as *const Slot<T>);
+    slot.value.store(null_mut(), Ordering::Release);
+    scm_gc_unprotect_object(obj);
+}
+
+/// Exclusive access to the value in a foreign object,
+/// from [`ForeignType::borrow_mut`].
+pub struct ForeignRef<'a, T> {
+    slot: &'a Slot<T>,
+    _object: SCM,
+}
+
+impl<T> ForeignRef<'_, T> {
+    fn value(&self) -> *mut T {
+        let value = self.slot.value.load(Ordering::Acquire);
+        // Only possible if this outlived the call the value was lent for.
+        assert!(
+            !value.is_null(),
+            "A lent value was given back while borrowed"
+        );
+        value
+    }
+}
+
+impl<T> Deref for ForeignRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value() }
+    }
+}
+
+impl<T> DerefMut for ForeignRef<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value() }
+    }
+}
+
+impl<T> Drop for ForeignRef<'_, T> {
+    fn drop(&mut self) {
+        self.slot.borrowed.store(false, Ordering::Release);
+    }
+}
+
+/// Types kept in foreign objects of their own [`ForeignType`], which
+/// [`scheme_fn`](crate::scheme_fn)s take by reference.
+pub trait ForeignObject: Sized + 'static {
+    fn foreign_type() -> &'static ForeignType<Self>;
+}
+
+/// The newtype name that tells the serializer and deserializer to pass
+/// objects through [`FOREIGN_OBJECT`].
+pub(crate) const FOREIGN_NEWTYPE: &str = "$btv_scheme::Foreign";
+
+thread_local! {
+    /// An object being handed between [`Foreign`] and the serializer or deserializer.
+    pub(crate) static FOREIGN_OBJECT: Cell<Option<SCM>> = const { Cell::new(None) };
+}
+
+/// A copy of a value kept in a foreign object, for serde types.
+/// It serializes to a new object owning a copy of the value, and deserializes
+/// from an object by copying its value out. This only works with btv-scheme's
+/// [`Serializer`](crate::Serializer) and [`Deserializer`](crate::Deserializer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Foreign<T>(pub T);
+
+impl<T: ForeignObject + Clone + Send> Serialize for Foreign<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let object = T::foreign_type().make(self.0.clone());
+        FOREIGN_OBJECT.with(|cell| cell.set(Some(object)));
+        serializer.serialize_newtype_struct(FOREIGN_NEWTYPE, &())
+    }
+}
+
+impl<'de, T: ForeignObject + Clone> Deserialize<'de> for Foreign<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T>(PhantomData<T>);
+
+        impl<'de, T: ForeignObject + Clone> de::Visitor<'de> for Visitor<T> {
+            type Value = Foreign<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a foreign object")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                let object = FOREIGN_OBJECT
+                    .with(Cell::take)
+                    .ok_or_else(|| E::custom("Foreign objects only deserialize from Scheme"))?;
+                T::foreign_type()
+                    .get(object)
+                    .map(Foreign)
+                    .map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(FOREIGN_NEWTYPE, Visitor(PhantomData))
+    }
+}
@@ -1,10 +1,13 @@
-use std::{convert::TryInto, num::TryFromIntError};
+use std::{cell::Cell, convert::TryInto, ffi::CStr, num::TryFromIntError};
 
 use rust_guile::{
-    scm_c_bytevector_set_x, scm_c_make_bytevector, scm_cons, scm_from_double, scm_from_int16,
+    scm_c_bytevector_set_x, scm_c_make_bytevector, scm_c_make_hash_table, scm_c_public_ref,
+    scm_c_vector_length, scm_c_vector_ref, scm_char_p, scm_cons, scm_from_double, scm_from_int16,
     scm_from_int32, scm_from_int64, scm_from_int8, scm_from_uint16, scm_from_uint32,
     scm_from_uint64, scm_from_uint8, scm_from_utf8_stringn, scm_from_utf8_symboln,
-    scm_integer_to_char, scm_is_truthy, try_scm_decons, try_scm_to_bytes, try_scm_to_char,
+    scm_hash_map_to_list, scm_hash_set_x, scm_hash_table_p, scm_integer_to_char, scm_is_bool,
+    scm_is_bytevector, scm_is_exact_integer, scm_is_real, scm_is_string, scm_is_symbol,
+    scm_is_truthy, scm_is_vector, scm_vector, try_scm_decons, try_scm_to_bytes, try_scm_to_char,
     try_scm_to_double, try_scm_to_signed, try_scm_to_string_or_sym, try_scm_to_sym,
     try_scm_to_unsigned, SCM, SCM_BOOL_F, SCM_BOOL_T, SCM_EOL,
 };
@@ -13,6 +16,59 @@ use serde::{
     ser::{self, SerializeStruct, SerializeStructVariant, SerializeTuple},
 };
 
+mod foreign;
+mod procedures;
+mod records;
+mod rooted;
+
+// So that `scheme_fn`'s expansion can name this crate in its tests.
+#[cfg(test)]
+extern crate self as btv_scheme;
+
+pub use foreign::{Foreign, ForeignObject, ForeignRef, ForeignType};
+use foreign::{FOREIGN_NEWTYPE, FOREIGN_OBJECT};
+pub use procedures::{guard, scheme_fns, SchemeFn, SchemeFnError};
+pub use records::{define_records, scheme_name};
+use records::{
+    is_record, make_record, newtype_fields, record_to_alist, record_type_name, record_values,
+    tuple_fields, variant_type_name,
+};
+pub use rooted::Rooted;
+
+/// Make a Rust function into a Scheme procedure named `name`, which
+/// [`scheme_fns`] lists for defining.
+///
+/// The procedure takes one argument per parameter. Parameters of type `SCM`
+/// are passed through, references borrow from [`ForeignObject`]s for the
+/// length of the call, and anything else is deserialized. The result is serialized, except that `()` is
+/// unspecified and an `SCM` is returned as is. Trailing `Option` parameters
+/// marked `#[optional]` are `None` when their argument is left out.
+///
+/// A function returning `Result<T, E>` needs `E:` [`SchemeFnError`], which
+/// picks the [`SerializerConfig`] for the conversions. Errors, including ones
+/// converting arguments and results, and panics are raised as Scheme
+/// exceptions by [`guard`].
+///
+/// ```ignore
+/// #[scheme_fn("fwm-get-point")]
+/// fn get_point(wm: &mut WmState) -> Result<ItemIdx, BindingError> {
+///     Ok(wm.point)
+/// }
+/// ```
+pub use btv_scheme_macros::scheme_fn;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::procedures::{foreign_ref, from_scm, to_scm};
+    pub use crate::SCM_UNDEFINED;
+    pub use inventory;
+    pub use rust_guile::{SCM, SCM_UNSPECIFIED};
+}
+
+/// Guile's `SCM_UNDEFINED`, which `rust_guile` doesn't bind: the value of
+/// an optional argument that wasn't passed, and the way to leave one out.
+pub const SCM_UNDEFINED: SCM = 0x904 as SCM;
+
 #[derive(Debug)]
 pub enum Error {
     ExpectedUnsignedInteger,
@@ -22,17 +78,110 @@ pub enum Error {
     ExpectedChar,
     ExpectedStringOrSymbol,
     ExpectedByteVector,
-    ExpectedList { n_elts: Option<usize> },
+    ExpectedList {
+        n_elts: Option<usize>,
+    },
     ExpectedNil,
-    ExpectedSymbol { sym: Option<String> },
+    ExpectedSymbol {
+        sym: Option<String>,
+    },
     ExpectedAlist,
     ExpectedSomething,
+    /// Expected an object of the named [`ForeignType`].
+    ExpectedForeign {
+        type_name: String,
+    },
+    /// A foreign object's value is already borrowed, e.g. by a caller further up the stack.
+    ForeignBorrowed,
+    /// A foreign object's value was lent for a call that has returned.
+    ForeignExpired,
+    /// A foreign object's value was lent by another thread, and can only be used there.
+    ForeignWrongThread,
+    /// `deserialize_any` found a value with no serde equivalent, like a procedure.
     NotSelfDescribing,
+    /// `deserialize_any` found an exact integer that fits in neither `i64` nor `u64`.
+    IntegerTooLarge,
+    /// An error reported by a `Serialize` or `Deserialize` impl,
+    /// e.g. an unknown variant or a missing field.
+    Custom(String),
+    /// `error` happened inside the datum, at `path`.
+    At {
+        /// Innermost segment first.
+        path: Vec<PathSegment>,
+        error: Box<Error>,
+    },
+}
+
+/// One step into a Scheme datum being deserialized.
+#[derive(Debug, Clone)]
+pub enum PathSegment {
+    /// The value of a struct field.
+    Field(&'static str),
+    /// The payload of an enum variant.
+    Variant(String),
+    /// An element of a list or tuple.
+    Index(usize),
+    /// The value for a key of a map, written out like a Rust literal.
+    Key(String),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Field(name) => write!(f, ".{}", name),
+            PathSegment::Variant(name) => write!(f, "::{}", name),
+            PathSegment::Index(i) => write!(f, "[{}]", i),
+            PathSegment::Key(key) => write!(f, "[{}]", key),
+        }
+    }
+}
+
+impl Error {
+    /// Record that this error happened inside `segment`.
+    pub fn at(self, segment: PathSegment) -> Self {
+        match self {
+            Error::At { mut path, error } => {
+                path.push(segment);
+                Error::At { path, error }
+            }
+            error => Error::At {
+                path: vec![segment],
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// The path to where the error happened, outermost segment first.
+    pub fn path(&self) -> impl Iterator<Item = &PathSegment> {
+        let path = match self {
+            Error::At { path, .. } => &path[..],
+            _ => &[],
+        };
+        path.iter().rev()
+    }
+
+    /// The error, without its path.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::At { error, .. } => error,
+            error => error,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::Custom(msg) => write!(f, "{}", msg),
+            Error::At { error, .. } => {
+                write!(f, "at ")?;
+                for segment in self.path() {
+                    write!(f, "{}", segment)?;
+                }
+                write!(f, ": {}", error)
+            }
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -43,7 +192,7 @@ impl ser::Error for Error {
     where
         T: std::fmt::Display,
     {
-        todo!()
+        Error::Custom(msg.to_string())
     }
 }
 
@@ -52,13 +201,97 @@ impl de::Error for Error {
     where
         T: std::fmt::Display,
     {
-        todo!()
+        Error::Custom(msg.to_string())
     }
 }
 
-pub enum ListSerializer {
-    Empty,
-    Heap { head: SCM, tail: SCM },
+/// How `Option`s are represented.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OptionRepr {
+    /// `None` is `'()` and `Some(x)` is `(x)`.
+    #[default]
+    List,
+    /// `None` is `#f` and `Some(x)` is just `x`. This reads more naturally in
+    /// Scheme, but `Some(false)` and `Some(None)` come back as `None`.
+    False,
+}
+
+/// How structs, and the payloads of struct variants, are represented.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StructRepr {
+    /// An alist from field names to values, in declaration order.
+    #[default]
+    Alist,
+    /// A Guile record. Its type is named after the struct, Scheme style
+    /// (`ChildLocation` becomes `child-location`), and is made the first time
+    /// it's needed. See [`define_records`] for accessors and predicates.
+    Record,
+    /// A vector of the values, in declaration order.
+    Vector,
+}
+
+/// How enum variants are represented.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EnumRepr {
+    /// The variant name, consed onto the payload if there is one:
+    /// `Unit`, `(Newtype . x)`, `(Tuple a . b)` and `(Struct . s)`.
+    #[default]
+    Pair,
+    /// A list of the variant name followed by the payload:
+    /// `(Unit)`, `(Newtype x)`, `(Tuple a b)` and `(Struct s)`.
+    TaggedList,
+    /// Variants with payloads are records, named after the enum and the
+    /// variant, like `move-cursor-into`. The payload of a newtype variant is
+    /// the field `value`, and those of a tuple variant are `0`, `1` and so on.
+    /// Unit variants are just their names, as with `Pair`.
+    Record,
+}
+
+/// How Rust values map onto Scheme data, where there's a choice.
+///
+/// Data must be deserialized with the config it was serialized with,
+/// except that structs are accepted in any [`StructRepr`], and enum variants
+/// as records whatever the [`EnumRepr`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SerializerConfig {
+    pub options: OptionRepr,
+    pub structs: StructRepr,
+    pub enums: EnumRepr,
+    /// Serialize maps as Guile hash tables, rather than alists.
+    /// This applies to nested maps too, but not to structs.
+    pub maps_as_hash_tables: bool,
+}
+
+/// The key under which `deserialize_any` gives a record's type name,
+/// like `move-cursor-into`.
+pub const RECORD_TYPE_KEY: &str = "$type";
+
+fn symbol(name: &str) -> SCM {
+    unsafe { scm_from_utf8_symboln(std::mem::transmute(name.as_ptr()), name.len() as u64) }
+}
+
+/// Look up `name` in the `(guile)` module.
+fn guile_ref(name: &CStr) -> SCM {
+    unsafe { scm_c_public_ref(c"guile".as_ptr(), name.as_ptr()) }
+}
+
+/// Append `value` to the list running from `head` to `tail`,
+/// which are both `'()` while it's empty.
+fn push_back(head: &mut SCM, tail: &mut SCM, value: SCM) {
+    let cell = scm_cons(value, SCM_EOL);
+    if *head == SCM_EOL {
+        *head = cell;
+    } else {
+        unsafe { std::ptr::write((*tail as *mut SCM).add(1), cell) };
+    }
+    *tail = cell;
+}
+
+pub struct ListSerializer {
+    ser: Serializer,
+    /// `'()` while the list is empty.
+    head: SCM,
+    tail: SCM,
 }
 
 impl ser::SerializeSeq for ListSerializer {
@@ -70,38 +303,40 @@ impl ser::SerializeSeq for ListSerializer {
     where
         T: serde::Serialize,
     {
-        let value = value.serialize(Serializer {
-            strings_as_syms: false,
-        })?;
-        match self {
-            ListSerializer::Empty => {
-                let cell = scm_cons(value, SCM_EOL);
-                *self = ListSerializer::Heap {
-                    head: cell,
-                    tail: cell,
-                };
-            }
-            ListSerializer::Heap { head: _, tail } => {
-                let cell = scm_cons(value, SCM_EOL);
-                unsafe { std::ptr::write((*tail as *mut SCM).add(1), cell) };
-                *tail = cell;
-            }
-        }
+        let value = value.serialize(self.ser.nested())?;
+        push_back(&mut self.head, &mut self.tail, value);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(match self {
-            ListSerializer::Empty => SCM_EOL,
-            ListSerializer::Heap { head, tail: _ } => head,
-        })
+        Ok(self.head)
     }
 }
 
-pub enum TupleSerializer {
+enum TupleState {
     Empty,
     One(SCM),
-    Heap { head: SCM, tail: SCM },
+    Heap {
+        head: SCM,
+        tail: SCM,
+    },
+    /// A proper list, for tuple variants in `EnumRepr::TaggedList`.
+    List {
+        head: SCM,
+        tail: SCM,
+    },
+    /// The values of a tuple variant's record, for `EnumRepr::Record`.
+    Record {
+        name: String,
+        head: SCM,
+        tail: SCM,
+        len: usize,
+    },
+}
+
+pub struct TupleSerializer {
+    ser: Serializer,
+    state: TupleState,
 }
 
 impl ser::SerializeTuple for TupleSerializer {
@@ -113,36 +348,44 @@ impl ser::SerializeTuple for TupleSerializer {
     where
         T: serde::Serialize,
     {
-        let value = value.serialize(Serializer {
-            strings_as_syms: false,
-        })?;
-        match self {
-            TupleSerializer::Empty => {
-                *self = TupleSerializer::One(value);
+        let value = value.serialize(self.ser.nested())?;
+        match &mut self.state {
+            TupleState::Empty => {
+                self.state = TupleState::One(value);
             }
-            TupleSerializer::One(old_value) => {
+            TupleState::One(old_value) => {
                 let pair = scm_cons(*old_value, value);
-                *self = TupleSerializer::Heap {
+                self.state = TupleState::Heap {
                     head: pair,
                     tail: pair,
                 }
             }
-            TupleSerializer::Heap { head: _, tail } => unsafe {
+            TupleState::Heap { head: _, tail } => unsafe {
                 let p_tail_cdr = (*tail as *mut SCM).add(1);
                 let tail_cdr = std::ptr::read(p_tail_cdr);
                 let new_pair = scm_cons(tail_cdr, value);
                 std::ptr::write(p_tail_cdr, new_pair);
                 *tail = new_pair;
             },
+            TupleState::List { head, tail } => push_back(head, tail, value),
+            TupleState::Record {
+                head, tail, len, ..
+            } => {
+                push_back(head, tail, value);
+                *len += 1;
+            }
         }
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(match self {
-            TupleSerializer::Empty => panic!("Saw zero-element tuple, rather than \"Unit\""),
-            TupleSerializer::One(v) => v,
-            TupleSerializer::Heap { head, tail: _ } => head,
+        Ok(match self.state {
+            TupleState::Empty => panic!("Saw zero-element tuple, rather than \"Unit\""),
+            TupleState::One(v) => v,
+            TupleState::Heap { head, tail: _ } | TupleState::List { head, tail: _ } => head,
+            TupleState::Record {
+                name, head, len, ..
+            } => make_record(name, tuple_fields(len), head),
         })
     }
 }
@@ -182,7 +425,11 @@ impl ser::SerializeTupleVariant for TupleSerializer {
 }
 
 pub struct MapSerializer {
-    list: SCM,
+    ser: Serializer,
+    /// An alist, or a hash table if `maps_as_hash_tables` is set.
+    map: SCM,
+    /// The alist's last pair.
+    tail: SCM,
     new_key: Option<SCM>,
 }
 
@@ -196,9 +443,7 @@ impl ser::SerializeMap for MapSerializer {
         T: serde::Serialize,
     {
         assert!(self.new_key.is_none());
-        self.new_key = Some(key.serialize(Serializer {
-            strings_as_syms: true,
-        })?);
+        self.new_key = Some(key.serialize(self.ser.key())?);
         Ok(())
     }
 
@@ -207,22 +452,45 @@ impl ser::SerializeMap for MapSerializer {
         T: serde::Serialize,
     {
         let key = self.new_key.take().unwrap();
-        let value = value.serialize(Serializer {
-            strings_as_syms: false,
-        })?;
-        let new_car = scm_cons(key, value);
-        let new_list = scm_cons(new_car, self.list);
-        self.list = new_list;
+        let value = value.serialize(self.ser.nested())?;
+        if self.ser.config.maps_as_hash_tables {
+            unsafe { scm_hash_set_x(self.map, key, value) };
+        } else {
+            push_back(&mut self.map, &mut self.tail, scm_cons(key, value));
+        }
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
         assert!(self.new_key.is_none());
-        Ok(self.list)
+        Ok(self.map)
     }
 }
 
-pub struct StructSerializer(SCM);
+pub struct StructSerializer {
+    ser: Serializer,
+    repr: StructRepr,
+    /// The record type's name.
+    name: String,
+    /// The fields seen so far, if making a record.
+    fields: Vec<&'static str>,
+    /// Alist entries for `StructRepr::Alist`, otherwise just the values.
+    head: SCM,
+    tail: SCM,
+}
+
+impl StructSerializer {
+    fn new(ser: Serializer, repr: StructRepr, name: String) -> Self {
+        StructSerializer {
+            ser,
+            repr,
+            name,
+            fields: vec![],
+            head: SCM_EOL,
+            tail: SCM_EOL,
+        }
+    }
+}
 
 impl SerializeStruct for StructSerializer {
     type Ok = SCM;
@@ -237,21 +505,28 @@ impl SerializeStruct for StructSerializer {
     where
         T: serde::Serialize,
     {
-        let new_car = unsafe {
-            scm_cons(
-                scm_from_utf8_symboln(std::mem::transmute(key.as_ptr()), key.len() as u64),
-                value.serialize(Serializer {
-                    strings_as_syms: false,
-                })?,
-            )
+        let value = value.serialize(self.ser.nested())?;
+        let entry = match self.repr {
+            StructRepr::Alist => scm_cons(symbol(key), value),
+            StructRepr::Record => {
+                self.fields.push(key);
+                value
+            }
+            StructRepr::Vector => value,
         };
-        let new_list = scm_cons(new_car, self.0);
-        self.0 = new_list;
+        push_back(&mut self.head, &mut self.tail, entry);
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.0)
+        Ok(match self.repr {
+            StructRepr::Alist => self.head,
+            StructRepr::Record => {
+                let fields = self.fields.iter().map(|f| f.to_string()).collect();
+                make_record(self.name, fields, self.head)
+            }
+            StructRepr::Vector => unsafe { scm_vector(self.head) },
+        })
     }
 }
 
@@ -277,14 +552,52 @@ impl SerializeStructVariant for StructVariantSerializer {
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        let cdr = self.inner.end()?;
-        Ok(scm_cons(self.name_sym, cdr))
+        let enums = self.inner.ser.config.enums;
+        let payload = self.inner.end()?;
+        Ok(match enums {
+            EnumRepr::Pair => scm_cons(self.name_sym, payload),
+            EnumRepr::TaggedList => scm_cons(self.name_sym, scm_cons(payload, SCM_EOL)),
+            EnumRepr::Record => payload,
+        })
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Serializer {
     strings_as_syms: bool,
+    config: SerializerConfig,
+}
+
+impl Serializer {
+    pub fn new(config: SerializerConfig) -> Self {
+        Serializer {
+            strings_as_syms: false,
+            config,
+        }
+    }
+
+    /// Serialize maps as Guile hash tables, rather than alists.
+    /// This applies to nested maps too, but not to structs.
+    pub fn maps_as_hash_tables(mut self, yes: bool) -> Self {
+        self.config.maps_as_hash_tables = yes;
+        self
+    }
+
+    /// The serializer for values nested in this one.
+    fn nested(self) -> Self {
+        Self {
+            strings_as_syms: false,
+            ..self
+        }
+    }
+
+    /// The serializer for map keys, which are symbols when they're strings.
+    fn key(self) -> Self {
+        Self {
+            strings_as_syms: true,
+            ..self
+        }
+    }
 }
 
 impl ser::Serializer for Serializer {
@@ -373,14 +686,21 @@ impl ser::Serializer for Serializer {
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        Ok(SCM_EOL)
+        Ok(match self.config.options {
+            OptionRepr::List => SCM_EOL,
+            OptionRepr::False => SCM_BOOL_F,
+        })
     }
 
     fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: serde::Serialize,
     {
-        Ok(scm_cons(value.serialize(self)?, SCM_EOL))
+        let value = value.serialize(self)?;
+        Ok(match self.config.options {
+            OptionRepr::List => scm_cons(value, SCM_EOL),
+            OptionRepr::False => value,
+        })
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
@@ -397,25 +717,33 @@ impl ser::Serializer for Serializer {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        Ok(unsafe {
-            scm_from_utf8_symboln(std::mem::transmute(variant.as_ptr()), variant.len() as u64)
+        let sym = symbol(variant);
+        Ok(match self.config.enums {
+            EnumRepr::Pair | EnumRepr::Record => sym,
+            EnumRepr::TaggedList => scm_cons(sym, SCM_EOL),
         })
     }
 
+    /// [`Foreign`] values have already been made into objects.
     fn serialize_newtype_struct<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: serde::Serialize,
     {
+        if name == FOREIGN_NEWTYPE {
+            return FOREIGN_OBJECT
+                .with(Cell::take)
+                .ok_or_else(|| Error::Custom("Missing foreign object".to_string()));
+        }
         value.serialize(self)
     }
 
     fn serialize_newtype_variant<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         value: &T,
@@ -423,19 +751,32 @@ impl ser::Serializer for Serializer {
     where
         T: serde::Serialize,
     {
-        let car = unsafe {
-            scm_from_utf8_symboln(std::mem::transmute(variant.as_ptr()), variant.len() as u64)
-        };
+        let car = symbol(variant);
         let cdr = value.serialize(self)?;
-        Ok(scm_cons(car, cdr))
+        Ok(match self.config.enums {
+            EnumRepr::Pair => scm_cons(car, cdr),
+            EnumRepr::TaggedList => scm_cons(car, scm_cons(cdr, SCM_EOL)),
+            EnumRepr::Record => make_record(
+                variant_type_name(name, variant),
+                newtype_fields(),
+                scm_cons(cdr, SCM_EOL),
+            ),
+        })
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        Ok(ListSerializer::Empty)
+        Ok(ListSerializer {
+            ser: self,
+            head: SCM_EOL,
+            tail: SCM_EOL,
+        })
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        Ok(TupleSerializer::Empty)
+        Ok(TupleSerializer {
+            ser: self,
+            state: TupleState::Empty,
+        })
     }
 
     fn serialize_tuple_struct(
@@ -443,60 +784,103 @@ impl ser::Serializer for Serializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Ok(TupleSerializer::Empty)
+        Ok(TupleSerializer {
+            ser: self,
+            state: TupleState::Empty,
+        })
     }
 
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        let car = unsafe {
-            scm_from_utf8_symboln(std::mem::transmute(variant.as_ptr()), variant.len() as u64)
+        let car = symbol(variant);
+        let state = match self.config.enums {
+            EnumRepr::Pair => TupleState::One(car),
+            EnumRepr::TaggedList => {
+                let cell = scm_cons(car, SCM_EOL);
+                TupleState::List {
+                    head: cell,
+                    tail: cell,
+                }
+            }
+            EnumRepr::Record => TupleState::Record {
+                name: variant_type_name(name, variant),
+                head: SCM_EOL,
+                tail: SCM_EOL,
+                len: 0,
+            },
         };
-        Ok(TupleSerializer::One(car))
+        Ok(TupleSerializer { ser: self, state })
     }
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        let map = if self.config.maps_as_hash_tables {
+            unsafe { scm_c_make_hash_table(len.unwrap_or(0) as u64) }
+        } else {
+            SCM_EOL
+        };
         Ok(MapSerializer {
-            list: SCM_EOL,
+            ser: self,
+            map,
+            tail: SCM_EOL,
             new_key: None,
         })
     }
 
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(StructSerializer(SCM_EOL))
+        Ok(StructSerializer::new(
+            self,
+            self.config.structs,
+            scheme_name(name),
+        ))
     }
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        let name_sym = unsafe {
-            scm_from_utf8_symboln(std::mem::transmute(variant.as_ptr()), variant.len() as u64)
+        let repr = match self.config.enums {
+            EnumRepr::Record => StructRepr::Record,
+            _ => self.config.structs,
         };
         Ok(StructVariantSerializer {
-            name_sym,
-            inner: StructSerializer(SCM_EOL),
+            name_sym: symbol(variant),
+            inner: StructSerializer::new(self, repr, variant_type_name(name, variant)),
         })
     }
 }
 
 pub struct Deserializer {
     pub scm: SCM,
+    pub config: SerializerConfig,
+}
+
+impl Deserializer {
+    /// A deserializer for data serialized with the default config.
+    pub fn new(scm: SCM) -> Self {
+        Self::with_config(scm, SerializerConfig::default())
+    }
+
+    pub fn with_config(scm: SCM, config: SerializerConfig) -> Self {
+        Deserializer { scm, config }
+    }
 }
 
 struct ListAccess {
     scm: SCM,
+    index: usize,
+    config: SerializerConfig,
 }
 
 impl<'de> de::SeqAccess<'de> for ListAccess {
@@ -509,16 +893,23 @@ impl<'de> de::SeqAccess<'de> for ListAccess {
         if self.scm == SCM_EOL {
             Ok(None)
         } else {
-            let (car, cdr) =
-                try_scm_decons(self.scm).ok_or(Error::ExpectedList { n_elts: None })?;
+            let index = self.index;
+            let (car, cdr) = try_scm_decons(self.scm).ok_or_else(|| {
+                Error::ExpectedList { n_elts: None }.at(PathSegment::Index(index))
+            })?;
             self.scm = cdr;
-            seed.deserialize(Deserializer { scm: car }).map(Some)
+            self.index += 1;
+            seed.deserialize(Deserializer::with_config(car, self.config))
+                .map(Some)
+                .map_err(|e| e.at(PathSegment::Index(index)))
         }
     }
 }
 
 struct TupleAccess {
     scm: Option<SCM>,
+    index: usize,
+    config: SerializerConfig,
 }
 
 impl<'de> de::SeqAccess<'de> for TupleAccess {
@@ -541,26 +932,181 @@ impl<'de> de::SeqAccess<'de> for TupleAccess {
                         scm
                     }
                 };
-                seed.deserialize(Deserializer { scm: next }).map(Some)
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(Deserializer::with_config(next, self.config))
+                    .map(Some)
+                    .map_err(|e| e.at(PathSegment::Index(index)))
             }
         }
     }
 }
 
+struct VectorAccess {
+    scm: SCM,
+    index: usize,
+    len: usize,
+    config: SerializerConfig,
+}
+
+impl VectorAccess {
+    /// Access to `scm`'s elements, if it's a vector.
+    fn new(scm: SCM, config: SerializerConfig) -> Option<Self> {
+        unsafe {
+            (scm_is_vector(scm) != 0).then(|| VectorAccess {
+                scm,
+                index: 0,
+                len: scm_c_vector_length(scm) as usize,
+                config,
+            })
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for VectorAccess {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let index = self.index;
+        self.index += 1;
+        let elt = unsafe { scm_c_vector_ref(self.scm, index as u64) };
+        seed.deserialize(Deserializer::with_config(elt, self.config))
+            .map(Some)
+            .map_err(|e| e.at(PathSegment::Index(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+/// Map access to an alist.
+struct AlistMapAccess {
+    scm: SCM,
+    /// The key whose value is next.
+    key: Option<String>,
+    /// Hand integer keys to the visitor as strings, as self-describing
+    /// formats like JSON need.
+    integer_keys_as_strings: bool,
+    config: SerializerConfig,
+}
+
+impl<'de> de::MapAccess<'de> for AlistMapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.scm == SCM_EOL {
+            return Ok(None);
+        }
+        let (car, _) = try_scm_decons(self.scm).ok_or(Error::ExpectedAlist)?;
+        let (key, _) = try_scm_decons(car).ok_or(Error::ExpectedAlist)?;
+        self.key = try_scm_to_string_or_sym(key)
+            .map(|key| format!("{:?}", key))
+            .or_else(|| try_scm_to_signed(key).map(|i| i.to_string()));
+        if self.integer_keys_as_strings && unsafe { scm_is_exact_integer(key) } != 0 {
+            let key = self.key.clone().ok_or(Error::IntegerTooLarge)?;
+            return seed
+                .deserialize(de::IntoDeserializer::<Error>::into_deserializer(key))
+                .map(Some);
+        }
+        seed.deserialize(Deserializer::with_config(key, self.config))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let (car, cdr) = try_scm_decons(self.scm).ok_or(Error::ExpectedAlist)?;
+        let (_, value) = try_scm_decons(car).ok_or(Error::ExpectedAlist)?;
+        self.scm = cdr;
+        let key = self.key.take().unwrap_or_default();
+        seed.deserialize(Deserializer::with_config(value, self.config))
+            .map_err(|e| e.at(PathSegment::Key(key)))
+    }
+}
+
+/// Whether `scm` is a proper list of pairs whose cars are all symbols,
+/// which is how structs and maps are serialized.
+fn is_symbol_alist(mut scm: SCM) -> bool {
+    if scm == SCM_EOL {
+        return false;
+    }
+    while scm != SCM_EOL {
+        let Some((car, cdr)) = try_scm_decons(scm) else {
+            return false;
+        };
+        match try_scm_decons(car) {
+            Some((key, _)) if try_scm_to_sym(key).is_some() => {}
+            _ => return false,
+        }
+        scm = cdr;
+    }
+    true
+}
+
+/// Whether `scm` is a list ending in `'()`.
+fn is_proper_list(mut scm: SCM) -> bool {
+    while scm != SCM_EOL {
+        match try_scm_decons(scm) {
+            Some((_, cdr)) => scm = cdr,
+            None => return false,
+        }
+    }
+    true
+}
+
 struct AlistStructAccess {
     fields: Vec<(Option<SCM>, &'static str)>,
     scm: SCM,
+    config: SerializerConfig,
 }
 
 struct VariantAccess {
     scm: Option<SCM>,
+    /// The variant's name, for error paths.
+    name: String,
+    config: SerializerConfig,
+    /// Whether `scm` is the variant's record.
+    record: bool,
+}
+
+impl VariantAccess {
+    fn segment(&self) -> PathSegment {
+        PathSegment::Variant(self.name.clone())
+    }
+
+    /// The single value in a newtype or struct variant,
+    /// or the variant's record.
+    fn payload(&self) -> Result<SCM, Error> {
+        let scm = self.scm.ok_or(Error::ExpectedSomething)?;
+        if self.record {
+            return Ok(scm);
+        }
+        match self.config.enums {
+            EnumRepr::Pair | EnumRepr::Record => Ok(scm),
+            EnumRepr::TaggedList => match try_scm_decons(scm) {
+                Some((car, SCM_EOL)) => Ok(car),
+                _ => Err(Error::ExpectedList { n_elts: Some(1) }.at(self.segment())),
+            },
+        }
+    }
 }
 
 impl<'de> de::VariantAccess<'de> for VariantAccess {
     type Error = Error;
 
     fn unit_variant(self) -> Result<(), Self::Error> {
-        if self.scm.is_none() {
+        if self.scm.is_none() || self.record {
             Ok(())
         } else {
             Err(Error::ExpectedSymbol { sym: None })
@@ -571,8 +1117,15 @@ impl<'de> de::VariantAccess<'de> for VariantAccess {
     where
         T: de::DeserializeSeed<'de>,
     {
-        let scm = self.scm.ok_or(Error::ExpectedSomething)?;
-        seed.deserialize(Deserializer { scm })
+        let segment = self.segment();
+        let mut scm = self.payload()?;
+        if self.record {
+            scm = try_scm_decons(record_values(scm))
+                .ok_or(Error::ExpectedSomething)?
+                .0;
+        }
+        seed.deserialize(Deserializer::with_config(scm, self.config))
+            .map_err(|e| e.at(segment))
     }
 
     fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
@@ -580,8 +1133,19 @@ impl<'de> de::VariantAccess<'de> for VariantAccess {
         V: de::Visitor<'de>,
     {
         use de::Deserializer as _;
+        let segment = self.segment();
         let scm = self.scm.ok_or(Error::ExpectedSomething)?;
-        Deserializer { scm }.deserialize_tuple(len, visitor)
+        if self.record {
+            return Deserializer::with_config(record_values(scm), self.config)
+                .deserialize_seq(visitor)
+                .map_err(|e| e.at(segment));
+        }
+        let de = Deserializer::with_config(scm, self.config);
+        match self.config.enums {
+            EnumRepr::Pair | EnumRepr::Record => de.deserialize_tuple(len, visitor),
+            EnumRepr::TaggedList => de.deserialize_seq(visitor),
+        }
+        .map_err(|e| e.at(segment))
     }
 
     fn struct_variant<V>(
@@ -593,13 +1157,19 @@ impl<'de> de::VariantAccess<'de> for VariantAccess {
         V: de::Visitor<'de>,
     {
         use de::Deserializer as _;
-        let scm = self.scm.ok_or(Error::ExpectedSomething)?;
-        Deserializer { scm }.deserialize_struct("", fields, visitor)
+        let segment = self.segment();
+        let scm = self.payload()?;
+        Deserializer::with_config(scm, self.config)
+            .deserialize_struct("", fields, visitor)
+            .map_err(|e| e.at(segment))
     }
 }
 
 struct EnumAccess {
     scm: SCM,
+    config: SerializerConfig,
+    name: &'static str,
+    variants: &'static [&'static str],
 }
 
 impl<'de> de::EnumAccess<'de> for EnumAccess {
@@ -611,12 +1181,43 @@ impl<'de> de::EnumAccess<'de> for EnumAccess {
     where
         V: de::DeserializeSeed<'de>,
     {
+        if is_record(self.scm) {
+            let type_name = record_type_name(self.scm);
+            let variant = self
+                .variants
+                .iter()
+                .find(|v| variant_type_name(self.name, v) == type_name)
+                .ok_or_else(|| {
+                    Error::Custom(format!("{} isn't a variant of {}", type_name, self.name))
+                })?;
+            let value =
+                seed.deserialize(Deserializer::with_config(symbol(variant), self.config))?;
+            return Ok((
+                value,
+                VariantAccess {
+                    scm: Some(self.scm),
+                    name: variant.to_string(),
+                    config: self.config,
+                    record: true,
+                },
+            ));
+        }
         let (variant, rest) = match try_scm_decons(self.scm) {
+            Some((car, SCM_EOL)) if self.config.enums == EnumRepr::TaggedList => (car, None),
             Some((car, cdr)) => (car, Some(cdr)),
             None => (self.scm, None),
         };
-        let variant = seed.deserialize(Deserializer { scm: variant })?;
-        Ok((variant, VariantAccess { scm: rest }))
+        let name = try_scm_to_sym(variant).unwrap_or_default();
+        let variant = seed.deserialize(Deserializer::with_config(variant, self.config))?;
+        Ok((
+            variant,
+            VariantAccess {
+                scm: rest,
+                name,
+                config: self.config,
+                record: false,
+            },
+        ))
     }
 }
 
@@ -648,8 +1249,10 @@ impl<'de> de::SeqAccess<'de> for AlistStructAccess {
                     }
                 }
             }
-            let piece = self.fields.pop().unwrap().0.unwrap();
-            seed.deserialize(Deserializer { scm: piece }).map(Some)
+            let (piece, name) = self.fields.pop().unwrap();
+            seed.deserialize(Deserializer::with_config(piece.unwrap(), self.config))
+                .map(Some)
+                .map_err(|e| e.at(PathSegment::Field(name)))
         }
     }
 }
@@ -657,11 +1260,87 @@ impl<'de> de::SeqAccess<'de> for AlistStructAccess {
 impl<'de> de::Deserializer<'de> for Deserializer {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    /// Guess the type from the datum itself. This is ambiguous in a few places,
+    /// which are resolved as follows:
+    ///
+    /// * `'()` is unit, rather than `None` or an empty list.
+    /// * Symbols are strings.
+    /// * A nonempty list of pairs with symbol keys is a map, like a struct.
+    /// * A record is a map of its fields, plus [`RECORD_TYPE_KEY`] with the
+    ///   name of its type, so that enum variants can be told apart.
+    /// * Other proper lists and vectors are sequences, and improper lists
+    ///   are tuples, e.g. `(a . b)` is a pair.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::NotSelfDescribing)
+        let (scm, config) = (self.scm, self.config);
+        unsafe {
+            if scm == SCM_EOL {
+                visitor.visit_unit()
+            } else if scm_is_bool(scm) != 0 {
+                visitor.visit_bool(scm_is_truthy(scm))
+            } else if scm_is_exact_integer(scm) != 0 {
+                if let Some(u) = try_scm_to_unsigned(scm) {
+                    visitor.visit_u64(u)
+                } else if let Some(i) = try_scm_to_signed(scm) {
+                    visitor.visit_i64(i)
+                } else {
+                    Err(Error::IntegerTooLarge)
+                }
+            } else if scm_is_real(scm) != 0 {
+                visitor.visit_f64(try_scm_to_double(scm).ok_or(Error::ExpectedFloat)?)
+            } else if scm_is_truthy(scm_char_p(scm)) {
+                visitor.visit_char(try_scm_to_char(scm).ok_or(Error::ExpectedChar)?)
+            } else if scm_is_string(scm) != 0 || scm_is_symbol(scm) != 0 {
+                visitor.visit_string(
+                    try_scm_to_string_or_sym(scm).ok_or(Error::ExpectedStringOrSymbol)?,
+                )
+            } else if scm_is_bytevector(scm) != 0 {
+                visitor.visit_byte_buf(try_scm_to_bytes(scm).ok_or(Error::ExpectedByteVector)?)
+            } else if let Some(access) = VectorAccess::new(scm, config) {
+                visitor.visit_seq(access)
+            } else if is_symbol_alist(scm) {
+                visitor.visit_map(AlistMapAccess {
+                    scm,
+                    key: None,
+                    integer_keys_as_strings: false,
+                    config,
+                })
+            } else if is_proper_list(scm) {
+                visitor.visit_seq(ListAccess {
+                    scm,
+                    index: 0,
+                    config,
+                })
+            } else if try_scm_decons(scm).is_some() {
+                visitor.visit_seq(TupleAccess {
+                    scm: Some(scm),
+                    index: 0,
+                    config,
+                })
+            } else if scm_is_truthy(scm_hash_table_p(scm)) {
+                visitor.visit_map(AlistMapAccess {
+                    scm: scm_hash_map_to_list(guile_ref(c"cons"), scm),
+                    key: None,
+                    integer_keys_as_strings: true,
+                    config,
+                })
+            } else if is_record(scm) {
+                let type_name = symbol(&record_type_name(scm));
+                visitor.visit_map(AlistMapAccess {
+                    scm: scm_cons(
+                        scm_cons(symbol(RECORD_TYPE_KEY), type_name),
+                        record_to_alist(scm),
+                    ),
+                    key: None,
+                    integer_keys_as_strings: false,
+                    config,
+                })
+            } else {
+                Err(Error::NotSelfDescribing)
+            }
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -771,11 +1450,13 @@ impl<'de> de::Deserializer<'de> for Deserializer {
         visitor.visit_char(val)
     }
 
+    /// Strings can't be borrowed from Guile, so this hands over an owned copy;
+    /// types that insist on `&'de str` can't be deserialized.
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_string(visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -786,11 +1467,12 @@ impl<'de> de::Deserializer<'de> for Deserializer {
         visitor.visit_string(val)
     }
 
+    /// Like `deserialize_str`, this always hands over an owned copy.
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_byte_buf(visitor)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -805,12 +1487,23 @@ impl<'de> de::Deserializer<'de> for Deserializer {
     where
         V: de::Visitor<'de>,
     {
-        if self.scm == SCM_EOL {
-            visitor.visit_none()
-        } else if let Some((car, SCM_EOL)) = try_scm_decons(self.scm) {
-            visitor.visit_some(Deserializer { scm: car })
-        } else {
-            Err(Error::ExpectedList { n_elts: Some(1) })
+        match self.config.options {
+            OptionRepr::List => {
+                if self.scm == SCM_EOL {
+                    visitor.visit_none()
+                } else if let Some((car, SCM_EOL)) = try_scm_decons(self.scm) {
+                    visitor.visit_some(Deserializer::with_config(car, self.config))
+                } else {
+                    Err(Error::ExpectedList { n_elts: Some(1) })
+                }
+            }
+            OptionRepr::False => {
+                if self.scm == SCM_BOOL_F {
+                    visitor.visit_none()
+                } else {
+                    visitor.visit_some(self)
+                }
+            }
         }
     }
 
@@ -845,6 +1538,8 @@ impl<'de> de::Deserializer<'de> for Deserializer {
         }
     }
 
+    /// Newtypes are transparent, matching `serialize_newtype_struct`.
+    /// [`Foreign`] values are copied out of their objects.
     fn deserialize_newtype_struct<V>(
         self,
         name: &'static str,
@@ -853,22 +1548,40 @@ impl<'de> de::Deserializer<'de> for Deserializer {
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        if name == FOREIGN_NEWTYPE {
+            FOREIGN_OBJECT.with(|cell| cell.set(Some(self.scm)));
+            return visitor.visit_unit();
+        }
+        visitor.visit_newtype_struct(self)
     }
 
+    /// Sequences can be lists or vectors.
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(ListAccess { scm: self.scm })
+        if let Some(access) = VectorAccess::new(self.scm, self.config) {
+            return visitor.visit_seq(access);
+        }
+        visitor.visit_seq(ListAccess {
+            scm: self.scm,
+            index: 0,
+            config: self.config,
+        })
     }
 
+    /// Tuples can be (possibly improper) lists or vectors.
     fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
+        if let Some(access) = VectorAccess::new(self.scm, self.config) {
+            return visitor.visit_seq(access);
+        }
         visitor.visit_seq(TupleAccess {
             scm: Some(self.scm),
+            index: 0,
+            config: self.config,
         })
     }
 
@@ -884,13 +1597,30 @@ impl<'de> de::Deserializer<'de> for Deserializer {
         self.deserialize_tuple(len, visitor)
     }
 
+    /// Maps can be alists, with keys of any type, or Guile hash tables.
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let alist = unsafe {
+            if scm_is_truthy(scm_hash_table_p(self.scm)) {
+                scm_hash_map_to_list(guile_ref(c"cons"), self.scm)
+            } else {
+                self.scm
+            }
+        };
+        if !is_proper_list(alist) {
+            return Err(Error::ExpectedAlist);
+        }
+        visitor.visit_map(AlistMapAccess {
+            scm: alist,
+            key: None,
+            integer_keys_as_strings: false,
+            config: self.config,
+        })
     }
 
+    /// Structs can be in any `StructRepr`: alists, records or vectors.
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
@@ -900,24 +1630,39 @@ impl<'de> de::Deserializer<'de> for Deserializer {
     where
         V: de::Visitor<'de>,
     {
+        if let Some(access) = VectorAccess::new(self.scm, self.config) {
+            return visitor.visit_seq(access);
+        }
+        let scm = if is_record(self.scm) {
+            record_to_alist(self.scm)
+        } else {
+            self.scm
+        };
         let mut fields: Vec<_> = fields.iter().map(|f| (None, *f)).collect();
         fields.reverse();
         visitor.visit_seq(AlistStructAccess {
             fields,
-            scm: self.scm,
+            scm,
+            config: self.config,
         })
     }
 
+    /// Variants can also be records, whatever the `EnumRepr`.
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
+        name: &'static str,
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_enum(EnumAccess { scm: self.scm })
+        visitor.visit_enum(EnumAccess {
+            scm: self.scm,
+            config: self.config,
+            name,
+            variants,
+        })
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -932,6 +1677,11 @@ impl<'de> de::Deserializer<'de> for Deserializer {
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        // Nothing needs to be read, and this way values with no serde
+        // equivalent can be skipped too.
+        visitor.visit_unit()
     }
 }
+
+#[cfg(test)]
+mod tests;
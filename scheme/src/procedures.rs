@@ -0,0 +1,160 @@
+//! Support for [`scheme_fn`](crate::scheme_fn): the registry of procedures
+//! it makes, and the conversions and error handling their wrappers use.
+
+use std::{
+    any::Any,
+    ffi::{c_void, CStr},
+    fmt::Display,
+    os::raw::c_char,
+    panic::AssertUnwindSafe,
+};
+
+use rust_guile::{
+    scm_c_define_gsubr, scm_error, scm_from_utf8_stringn, scm_from_utf8_symbol, scm_list_1, SCM,
+    SCM_BOOL_F,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    foreign::{ForeignObject, ForeignRef},
+    Deserializer, EnumRepr, Error, OptionRepr, Serializer, SerializerConfig, StructRepr,
+};
+
+/// A Scheme procedure made by [`scheme_fn`](crate::scheme_fn).
+#[derive(Debug)]
+pub struct SchemeFn {
+    pub name: &'static CStr,
+    pub required: i32,
+    pub optional: i32,
+    subr: *mut c_void,
+}
+
+// `subr` points to code, not data.
+unsafe impl Sync for SchemeFn {}
+
+impl SchemeFn {
+    #[doc(hidden)]
+    pub const fn __new(
+        name: &'static CStr,
+        required: i32,
+        optional: i32,
+        subr: *mut c_void,
+    ) -> Self {
+        SchemeFn {
+            name,
+            required,
+            optional,
+            subr,
+        }
+    }
+
+    /// Define the procedure in the current module.
+    ///
+    /// # Safety
+    /// This must be called in Guile mode.
+    pub unsafe fn define(&self) {
+        scm_c_define_gsubr(
+            self.name.as_ptr(),
+            self.required,
+            self.optional,
+            0,
+            self.subr,
+        );
+    }
+}
+
+inventory::collect!(SchemeFn);
+
+/// Every procedure made by [`scheme_fn`](crate::scheme_fn) in the program,
+/// in no particular order.
+pub fn scheme_fns() -> impl Iterator<Item = &'static SchemeFn> {
+    inventory::iter::<SchemeFn>.into_iter()
+}
+
+/// The error type of a [`scheme_fn`](crate::scheme_fn). It decides how the
+/// function's arguments and result are converted, and which exception its
+/// failures raise.
+pub trait SchemeFnError: From<Error> + Display {
+    /// The config arguments are deserialized, and results serialized, with.
+    const CONFIG: SerializerConfig;
+
+    /// The key of the exception raised for this error, like `misc-error`.
+    fn key(&self) -> &'static CStr;
+}
+
+/// Functions that don't return a `Result` can only fail to convert their
+/// arguments, which raises `wrong-type-arg`. Functions that return their own
+/// `Error::Custom` raise `misc-error`.
+impl SchemeFnError for Error {
+    const CONFIG: SerializerConfig = SerializerConfig {
+        options: OptionRepr::List,
+        structs: StructRepr::Alist,
+        enums: EnumRepr::Pair,
+        maps_as_hash_tables: false,
+    };
+
+    fn key(&self) -> &'static CStr {
+        match self {
+            Error::Custom(_) => c"misc-error",
+            _ => c"wrong-type-arg",
+        }
+    }
+}
+
+/// Throw a Scheme exception to the innermost `catch` for `key`.
+///
+/// Guile unwinds with `longjmp`, so this never returns, and nothing that
+/// needs dropping may be alive in the Rust frames it unwinds through.
+#[allow(unreachable_code)]
+unsafe fn raise_scm_error(key: &CStr, subr: &CStr, message: String) -> ! {
+    let message_scm =
+        scm_from_utf8_stringn(message.as_ptr() as *const c_char, message.len() as u64);
+    drop(message);
+    scm_error(
+        scm_from_utf8_symbol(key.as_ptr()),
+        subr.as_ptr(),
+        c"~A".as_ptr(),
+        scm_list_1(message_scm),
+        SCM_BOOL_F,
+    );
+    unreachable!("scm_error returned")
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Rust code panicked".to_string()
+    }
+}
+
+/// Run the body of the procedure named `subr`, raising its errors and panics
+/// as Scheme exceptions instead of letting them cross the FFI boundary.
+///
+/// Errors are raised with [`SchemeFnError::key`], and panics with `rust-panic`.
+/// Everything `body` owns has been dropped by the time Guile unwinds.
+///
+/// # Safety
+/// This must be called in Guile mode, from a procedure called by Scheme.
+pub unsafe fn guard<E: SchemeFnError>(subr: &CStr, body: impl FnOnce() -> Result<SCM, E>) -> SCM {
+    let (key, message) = match std::panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(scm)) => return scm,
+        Ok(Err(e)) => (e.key(), e.to_string()),
+        Err(payload) => (c"rust-panic", panic_message(&*payload)),
+    };
+    raise_scm_error(key, subr, message)
+}
+
+pub fn foreign_ref<T: ForeignObject>(scm: SCM) -> Result<ForeignRef<'static, T>, Error> {
+    T::foreign_type().borrow_mut(scm)
+}
+
+pub fn from_scm<'de, T: Deserialize<'de>>(scm: SCM, config: SerializerConfig) -> Result<T, Error> {
+    T::deserialize(Deserializer::with_config(scm, config))
+}
+
+pub fn to_scm<T: Serialize + ?Sized>(value: &T, config: SerializerConfig) -> Result<SCM, Error> {
+    value.serialize(Serializer::new(config))
+}
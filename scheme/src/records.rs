@@ -0,0 +1,652 @@
+//! Guile records for structs and enum variants, as used by
+//! [`StructRepr::Record`](crate::StructRepr::Record) and
+//! [`EnumRepr::Record`](crate::EnumRepr::Record), and Scheme bindings for them.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    sync::{Mutex, OnceLock},
+};
+
+use rust_guile::{
+    scm_apply_0, scm_c_define, scm_call_1, scm_call_2, scm_cons, scm_gc_protect_object,
+    scm_is_truthy, try_scm_decons, try_scm_to_sym, SCM, SCM_EOL,
+};
+use serde::de::{self, IntoDeserializer};
+
+use crate::{guile_ref, push_back, symbol, Error};
+
+/// The Scheme spelling of a Rust identifier:
+/// `MoveCursor` becomes `move-cursor`, and `last_focused` becomes `last-focused`.
+pub fn scheme_name(ident: &str) -> String {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut name = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            name.push('-');
+            continue;
+        }
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                name.push('-');
+            }
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
+
+/// The name of the record type for `variant` of the enum `name`,
+/// e.g. `move-cursor-into`.
+pub(crate) fn variant_type_name(name: &str, variant: &str) -> String {
+    format!("{}-{}", scheme_name(name), scheme_name(variant))
+}
+
+/// The fields of the record for a newtype variant.
+pub(crate) fn newtype_fields() -> Vec<String> {
+    vec!["value".to_string()]
+}
+
+/// The fields of the record for a tuple variant: `0`, `1`, and so on.
+pub(crate) fn tuple_fields(len: usize) -> Vec<String> {
+    (0..len).map(|i| i.to_string()).collect()
+}
+
+#[derive(Clone, Copy)]
+struct RecordType {
+    rtd: SCM,
+    constructor: SCM,
+}
+
+// These are protected from garbage collection for good, so can be shared.
+unsafe impl Send for RecordType {}
+
+/// Record types by name and fields.
+type RecordTypes = HashMap<(String, Vec<String>), RecordType>;
+
+static RECORD_TYPES: OnceLock<Mutex<RecordTypes>> = OnceLock::new();
+
+/// The record type named `name` with `fields`, which are Rust field names,
+/// making it if this is the first time it's needed.
+fn record_type(name: String, fields: Vec<String>) -> RecordType {
+    let types = RECORD_TYPES.get_or_init(Default::default);
+    *types
+        .lock()
+        .unwrap()
+        .entry((name, fields))
+        .or_insert_with_key(|(name, fields)| {
+            let mut field_syms = SCM_EOL;
+            for field in fields.iter().rev() {
+                field_syms = scm_cons(symbol(field), field_syms);
+            }
+            unsafe {
+                let rtd = scm_gc_protect_object(scm_call_2(
+                    guile_ref(c"make-record-type"),
+                    symbol(name),
+                    field_syms,
+                ));
+                let constructor =
+                    scm_gc_protect_object(scm_call_1(guile_ref(c"record-constructor"), rtd));
+                RecordType { rtd, constructor }
+            }
+        })
+}
+
+/// A record of the type named `name` with `fields`, holding the list `values`.
+pub(crate) fn make_record(name: String, fields: Vec<String>, values: SCM) -> SCM {
+    let ty = record_type(name, fields);
+    unsafe { scm_apply_0(ty.constructor, values) }
+}
+
+/// Whether `scm` is a Guile record, like those made for `StructRepr::Record`.
+pub(crate) fn is_record(scm: SCM) -> bool {
+    scm_is_truthy(unsafe { scm_call_1(guile_ref(c"record?"), scm) })
+}
+
+/// The name of the record `scm`'s type.
+pub(crate) fn record_type_name(scm: SCM) -> String {
+    unsafe {
+        let rtd = scm_call_1(guile_ref(c"record-type-descriptor"), scm);
+        try_scm_to_sym(scm_call_1(guile_ref(c"record-type-name"), rtd)).unwrap_or_default()
+    }
+}
+
+/// An alist from the record `scm`'s field names to their values.
+pub(crate) fn record_to_alist(scm: SCM) -> SCM {
+    let (mut head, mut tail) = (SCM_EOL, SCM_EOL);
+    unsafe {
+        let rtd = scm_call_1(guile_ref(c"record-type-descriptor"), scm);
+        let mut fields = scm_call_1(guile_ref(c"record-type-fields"), rtd);
+        while let Some((field, rest)) = try_scm_decons(fields) {
+            let accessor = scm_call_2(guile_ref(c"record-accessor"), rtd, field);
+            push_back(
+                &mut head,
+                &mut tail,
+                scm_cons(field, scm_call_1(accessor, scm)),
+            );
+            fields = rest;
+        }
+    }
+    head
+}
+
+/// A list of the values of the record `scm`'s fields.
+pub(crate) fn record_values(scm: SCM) -> SCM {
+    let (mut head, mut tail) = (SCM_EOL, SCM_EOL);
+    let mut alist = record_to_alist(scm);
+    while let Some((entry, rest)) = try_scm_decons(alist) {
+        let (_, value) = try_scm_decons(entry).unwrap();
+        push_back(&mut head, &mut tail, value);
+        alist = rest;
+    }
+    head
+}
+
+/// Bind `name` to `value` in the current module.
+fn define(name: &str, value: SCM) {
+    let name = CString::new(name).unwrap();
+    unsafe { scm_c_define(name.as_ptr(), value) };
+}
+
+/// Define Scheme bindings for the record types that values of `T` can be
+/// serialized as. For `struct ChildLocation { container, index }`, these are
+/// `<child-location>`, `make-child-location`, `child-location?`,
+/// `child-location-container` and `child-location-index`. Enum variants with
+/// payloads get the same, named like `move-cursor-into`; the payload of
+/// a newtype variant is called `value`, and those of a tuple variant `0`, `1`
+/// and so on.
+///
+/// The types are found by walking `T`'s `Deserialize` impl, trying each
+/// variant of every enum. Types that need `deserialize_any`,
+/// like untagged enums, can't be walked.
+pub fn define_records<T: de::Deserialize<'static>>() -> Result<(), Error> {
+    let mut trace = Trace::default();
+    // Keep going until every enum has had a chance to reach each of its
+    // variants without finding anything new.
+    let mut idle = 0;
+    while idle <= trace.n_variants() {
+        trace.progress = false;
+        trace.too_deep = false;
+        let result = T::deserialize(Tracer {
+            trace: &mut trace,
+            depth: 0,
+        });
+        if let Err(e) = result {
+            if !trace.too_deep {
+                return Err(e);
+            }
+        }
+        idle = if trace.progress { 0 } else { idle + 1 };
+    }
+    for (name, fields) in trace.records {
+        let ty = record_type(name.clone(), fields.clone());
+        define(&format!("<{}>", name), ty.rtd);
+        define(&format!("make-{}", name), ty.constructor);
+        unsafe {
+            define(
+                &format!("{}?", name),
+                scm_call_1(guile_ref(c"record-predicate"), ty.rtd),
+            );
+            for field in &fields {
+                define(
+                    &format!("{}-{}", name, scheme_name(field)),
+                    scm_call_2(guile_ref(c"record-accessor"), ty.rtd, symbol(field)),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How far the tracer goes into options, sequences, maps and enums,
+/// which can all be recursive.
+const TRACE_DEPTH: usize = 8;
+
+#[derive(Default)]
+struct Trace {
+    /// Record types found so far, by name and fields.
+    records: Vec<(String, Vec<String>)>,
+    /// For each enum, which variants have been tried, and how many times
+    /// one has been picked since they all were.
+    enums: HashMap<&'static str, (Vec<bool>, usize)>,
+    /// Whether this pass found anything new.
+    progress: bool,
+    /// Whether this pass gave up at `TRACE_DEPTH`.
+    too_deep: bool,
+}
+
+impl Trace {
+    fn n_variants(&self) -> usize {
+        self.enums.values().map(|(tried, _)| tried.len()).sum()
+    }
+
+    fn record(&mut self, name: String, fields: Vec<String>) {
+        if !self.records.iter().any(|(n, f)| *n == name && *f == fields) {
+            self.records.push((name, fields));
+            self.progress = true;
+        }
+    }
+
+    /// The variant to take for the enum `name`: an untried one if possible,
+    /// otherwise each in turn.
+    fn pick(&mut self, name: &'static str, n_variants: usize) -> usize {
+        let (tried, turns) = self
+            .enums
+            .entry(name)
+            .or_insert_with(|| (vec![false; n_variants], 0));
+        if let Some(i) = tried.iter().position(|tried| !tried) {
+            tried[i] = true;
+            self.progress = true;
+            i
+        } else {
+            *turns += 1;
+            *turns % n_variants
+        }
+    }
+}
+
+/// Deserializes a made-up value of any type, noting its record types.
+struct Tracer<'a> {
+    trace: &'a mut Trace,
+    depth: usize,
+}
+
+impl<'a> Tracer<'a> {
+    /// Whether possibly recursive parts of this value should be left out.
+    fn too_deep(&self) -> bool {
+        self.depth >= TRACE_DEPTH
+    }
+
+    /// A tracer for a possibly recursive part of this value.
+    fn deeper(self) -> Tracer<'a> {
+        Tracer {
+            trace: self.trace,
+            depth: self.depth + 1,
+        }
+    }
+
+    fn seq(self, len: usize) -> TraceSeq<'a> {
+        TraceSeq {
+            trace: self.trace,
+            depth: self.depth,
+            remaining: len,
+        }
+    }
+}
+
+struct TraceSeq<'a> {
+    trace: &'a mut Trace,
+    depth: usize,
+    remaining: usize,
+}
+
+impl TraceSeq<'_> {
+    fn tracer(&mut self) -> Tracer<'_> {
+        Tracer {
+            trace: self.trace,
+            depth: self.depth,
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for TraceSeq<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(self.tracer()).map(Some)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for TraceSeq<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(self.tracer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.tracer())
+    }
+}
+
+struct TraceEnum<'a> {
+    tracer: Tracer<'a>,
+    name: &'static str,
+    variant: &'static str,
+    index: u32,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for TraceEnum<'a> {
+    type Error = Error;
+
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let index: de::value::U32Deserializer<Error> = self.index.into_deserializer();
+        Ok((seed.deserialize(index)?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for TraceEnum<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        self.tracer
+            .trace
+            .record(variant_type_name(self.name, self.variant), newtype_fields());
+        seed.deserialize(self.tracer)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.tracer.trace.record(
+            variant_type_name(self.name, self.variant),
+            tuple_fields(len),
+        );
+        visitor.visit_seq(self.tracer.seq(len))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.tracer.trace.record(
+            variant_type_name(self.name, self.variant),
+            fields.iter().map(|f| f.to_string()).collect(),
+        );
+        visitor.visit_seq(self.tracer.seq(fields.len()))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Tracer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_char('\0')
+    }
+
+    /// Borrowed, so that types holding `&str` can be walked too.
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.too_deep() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self.deeper())
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Sequences, like maps, get one element, unless they're too deep.
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let len = usize::from(!self.too_deep());
+        visitor.visit_seq(self.deeper().seq(len))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_seq(self.seq(len))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_seq(self.seq(len))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let len = usize::from(!self.too_deep());
+        visitor.visit_map(self.deeper().seq(len))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.trace.record(
+            scheme_name(name),
+            fields.iter().map(|f| f.to_string()).collect(),
+        );
+        visitor.visit_seq(self.seq(fields.len()))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if variants.is_empty() {
+            return Err(Error::Custom(format!("{} has no variants", name)));
+        }
+        if self.too_deep() {
+            self.trace.too_deep = true;
+            return Err(Error::Custom(format!("{} nests too deeply to trace", name)));
+        }
+        let index = self.trace.pick(name, variants.len());
+        visitor.visit_enum(TraceEnum {
+            tracer: self.deeper(),
+            name,
+            variant: variants[index],
+            index: index as u32,
+        })
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
@@ -0,0 +1,56 @@
+//! GC roots for Scheme values held by Rust.
+
+use rust_guile::{scm_gc_protect_object, scm_gc_unprotect_object, SCM};
+
+/// A Scheme value that can't be garbage collected while this is alive.
+///
+/// Guile only scans the stack for roots, so any `SCM` Rust keeps elsewhere,
+/// like in a struct or a channel, must be held in one of these. Protection
+/// is counted, so clones are independent roots.
+#[derive(Debug)]
+pub struct Rooted(SCM);
+
+// Guile objects can be used from any thread in Guile mode.
+unsafe impl Send for Rooted {}
+unsafe impl Sync for Rooted {}
+
+impl Rooted {
+    // Guile accepts any value here, immediates included.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn new(scm: SCM) -> Self {
+        unsafe { scm_gc_protect_object(scm) };
+        Rooted(scm)
+    }
+
+    pub fn get(&self) -> SCM {
+        self.0
+    }
+
+    /// Release the value without unrooting it, e.g. to pass it through a
+    /// pipe. It stays rooted until it's given to [`from_raw`](Self::from_raw).
+    pub fn into_raw(self) -> SCM {
+        let scm = self.0;
+        std::mem::forget(self);
+        scm
+    }
+
+    /// Take back a value released by [`into_raw`](Self::into_raw).
+    ///
+    /// # Safety
+    /// `scm` must come from `into_raw`, and each call to it only be taken back once.
+    pub unsafe fn from_raw(scm: SCM) -> Self {
+        Rooted(scm)
+    }
+}
+
+impl Clone for Rooted {
+    fn clone(&self) -> Self {
+        Rooted::new(self.0)
+    }
+}
+
+impl Drop for Rooted {
+    fn drop(&mut self) {
+        unsafe { scm_gc_unprotect_object(self.0) };
+    }
+}
@@ -0,0 +1,575 @@
+//! Round-trip tests: serialize Rust values to Scheme and back,
+//! against a live Guile instance.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::fmt::Debug;
+
+use rust_guile::{
+    scm_c_eval_string, scm_c_lookup, scm_c_make_vector, scm_c_vector_set_x, scm_call_1, scm_call_2,
+    scm_gc, scm_init_guile, scm_variable_ref,
+};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+fn init() {
+    // Idempotent, and puts the calling thread (each test has its own) into Guile mode.
+    unsafe { scm_init_guile() };
+}
+
+fn round_trip_with<T>(config: SerializerConfig, value: T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    init();
+    let scm = value.serialize(Serializer::new(config)).expect("serialize");
+    let back = T::deserialize(Deserializer::with_config(scm, config)).expect("deserialize");
+    assert_eq!(value, back, "with {config:?}");
+}
+
+fn round_trip<T>(value: T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    round_trip_with(SerializerConfig::default(), value)
+}
+
+/// The keys of an alist, which must have symbol keys.
+fn alist_keys(mut alist: SCM) -> Vec<String> {
+    let mut keys = vec![];
+    while let Some((entry, rest)) = try_scm_decons(alist) {
+        let (key, _) = try_scm_decons(entry).expect("alist entry");
+        keys.push(try_scm_to_sym(key).expect("symbol key"));
+        alist = rest;
+    }
+    keys
+}
+
+fn vector(elts: &[u64]) -> SCM {
+    unsafe {
+        let v = scm_c_make_vector(elts.len() as u64, SCM_BOOL_F);
+        for (i, &elt) in elts.iter().enumerate() {
+            scm_c_vector_set_x(v, i as u64, scm_from_uint64(elt));
+        }
+        v
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct UnitStruct;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Newtype(usize);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct TupleStruct(u32, String, bool);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Struct {
+    id: Newtype,
+    name: String,
+    weights: Vec<f64>,
+    parent: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Enum {
+    Unit,
+    Newtype(i32),
+    Tuple(u8, char),
+    Struct { x: usize, y: usize },
+}
+
+/// Bytes, without pulling in `serde_bytes`.
+#[derive(PartialEq, Debug)]
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Bytes;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "bytes")
+            }
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                Ok(Bytes(v))
+            }
+        }
+        deserializer.deserialize_bytes(Visitor)
+    }
+}
+
+#[test]
+fn primitives() {
+    round_trip(true);
+    round_trip(false);
+    round_trip(i8::MIN);
+    round_trip(i16::MIN);
+    round_trip(i32::MIN);
+    round_trip(i64::MIN);
+    round_trip(u8::MAX);
+    round_trip(u16::MAX);
+    round_trip(u32::MAX);
+    round_trip(u64::MAX);
+    round_trip(1.5f32);
+    round_trip(-2.25f64);
+    round_trip('λ');
+    round_trip("hello".to_string());
+    round_trip(Bytes(vec![0, 1, 255]));
+}
+
+#[test]
+fn options_and_units() {
+    round_trip(None::<u32>);
+    round_trip(Some(3u32));
+    round_trip(Some("x".to_string()));
+    round_trip(());
+    round_trip(UnitStruct);
+}
+
+#[test]
+fn newtypes() {
+    round_trip(Newtype(7));
+    round_trip(vec![Newtype(1), Newtype(2)]);
+}
+
+#[test]
+fn sequences_and_tuples() {
+    round_trip(Vec::<u32>::new());
+    round_trip(vec![1u32, 2, 3]);
+    round_trip(vec![vec![1u8], vec![], vec![2, 3]]);
+    round_trip((1u32, -2i64));
+    round_trip((1u32, "two".to_string(), 3.0f64));
+    round_trip(TupleStruct(1, "one".to_string(), true));
+}
+
+#[test]
+fn maps() {
+    round_trip(BTreeMap::<String, u32>::new());
+    round_trip(BTreeMap::from([
+        ("a".to_string(), 1u32),
+        ("b".to_string(), 2),
+    ]));
+    round_trip(BTreeMap::from([
+        (1usize, "a".to_string()),
+        (2, "b".to_string()),
+    ]));
+    round_trip(HashMap::from([(1usize, vec![1u32]), (2, vec![])]));
+    let hash_tables = SerializerConfig {
+        maps_as_hash_tables: true,
+        ..Default::default()
+    };
+    round_trip_with(hash_tables, BTreeMap::<usize, u32>::new());
+    round_trip_with(hash_tables, BTreeMap::from([(1usize, 10u32), (2, 20)]));
+    round_trip_with(
+        hash_tables,
+        BTreeMap::from([("nested".to_string(), BTreeMap::from([(1usize, 1u32)]))]),
+    );
+}
+
+#[test]
+fn structs_and_enums() {
+    round_trip(Struct {
+        id: Newtype(3),
+        name: "root".to_string(),
+        weights: vec![0.5, 0.5],
+        parent: None,
+    });
+    round_trip(Struct {
+        id: Newtype(4),
+        name: String::new(),
+        weights: vec![],
+        parent: Some(3),
+    });
+    round_trip(Enum::Unit);
+    round_trip(Enum::Newtype(-1));
+    round_trip(Enum::Tuple(1, 'c'));
+    round_trip(Enum::Struct { x: 1, y: 2 });
+    round_trip(vec![Enum::Unit, Enum::Newtype(2)]);
+}
+
+#[test]
+fn declaration_order() {
+    init();
+    let scm = Struct {
+        id: Newtype(1),
+        name: String::new(),
+        weights: vec![],
+        parent: None,
+    }
+    .serialize(Serializer::default())
+    .unwrap();
+    assert_eq!(alist_keys(scm), ["id", "name", "weights", "parent"]);
+
+    let scm = BTreeMap::from([("b", 2u32), ("a", 1), ("c", 3)])
+        .serialize(Serializer::default())
+        .unwrap();
+    assert_eq!(alist_keys(scm), ["a", "b", "c"]);
+}
+
+#[test]
+fn configs() {
+    for options in [OptionRepr::List, OptionRepr::False] {
+        for structs in [StructRepr::Alist, StructRepr::Record, StructRepr::Vector] {
+            for enums in [EnumRepr::Pair, EnumRepr::TaggedList, EnumRepr::Record] {
+                let config = SerializerConfig {
+                    options,
+                    structs,
+                    enums,
+                    maps_as_hash_tables: false,
+                };
+                round_trip_with(config, None::<()>);
+                round_trip_with(config, Some(()));
+                round_trip_with(config, vec![Some(1u32), None]);
+                round_trip_with(
+                    config,
+                    Struct {
+                        id: Newtype(2),
+                        name: "child".to_string(),
+                        weights: vec![1.0],
+                        parent: Some(1),
+                    },
+                );
+                round_trip_with(config, Enum::Unit);
+                round_trip_with(config, Enum::Newtype(3));
+                round_trip_with(config, Enum::Tuple(4, 'x'));
+                round_trip_with(config, Enum::Struct { x: 5, y: 6 });
+                round_trip_with(config, Some(Enum::Newtype(7)));
+            }
+        }
+    }
+}
+
+#[test]
+fn scheme_names() {
+    assert_eq!(scheme_name("MoveCursor"), "move-cursor");
+    assert_eq!(scheme_name("Into"), "into");
+    assert_eq!(scheme_name("last_focused"), "last-focused");
+    assert_eq!(scheme_name("XWindow"), "x-window");
+    assert_eq!(scheme_name("Rgb8Color"), "rgb8-color");
+}
+
+#[test]
+fn record_bindings() {
+    init();
+    define_records::<Enum>().unwrap();
+    define_records::<Struct>().unwrap();
+    let ser = Serializer::new(SerializerConfig {
+        structs: StructRepr::Record,
+        enums: EnumRepr::Record,
+        ..Default::default()
+    });
+    let call = |name: &CStr, arg: SCM| unsafe {
+        scm_call_1(scm_variable_ref(scm_c_lookup(name.as_ptr())), arg)
+    };
+
+    let scm = Enum::Struct { x: 1, y: 2 }.serialize(ser).unwrap();
+    assert!(scm_is_truthy(call(c"enum-struct?", scm)));
+    assert!(!scm_is_truthy(call(c"enum-tuple?", scm)));
+    assert_eq!(try_scm_to_unsigned(call(c"enum-struct-y", scm)), Some(2));
+    let scm = Enum::Tuple(3, 'c').serialize(ser).unwrap();
+    assert_eq!(try_scm_to_unsigned(call(c"enum-tuple-0", scm)), Some(3));
+    let scm = Enum::Newtype(-4).serialize(ser).unwrap();
+    assert_eq!(
+        try_scm_to_signed(call(c"enum-newtype-value", scm)),
+        Some(-4)
+    );
+    assert_eq!(
+        try_scm_to_sym(Enum::Unit.serialize(ser).unwrap()).as_deref(),
+        Some("Unit")
+    );
+
+    let scm = Struct {
+        id: Newtype(5),
+        name: "five".to_string(),
+        weights: vec![],
+        parent: None,
+    }
+    .serialize(ser)
+    .unwrap();
+    assert!(scm_is_truthy(call(c"struct?", scm)));
+    assert_eq!(
+        try_scm_to_string_or_sym(call(c"struct-name", scm)).as_deref(),
+        Some("five")
+    );
+
+    // Records made in Scheme can be read back, whatever the config.
+    let scm = unsafe { scm_c_eval_string(c"(make-enum-struct 6 7)".as_ptr()) };
+    assert_eq!(
+        Enum::deserialize(Deserializer::new(scm)).unwrap(),
+        Enum::Struct { x: 6, y: 7 }
+    );
+}
+
+#[scheme_fn("test-add")]
+fn add(x: i64, #[optional] y: Option<i64>) -> i64 {
+    x + y.unwrap_or(1)
+}
+
+#[scheme_fn("test-fail")]
+fn fail(message: String) -> Result<(), Error> {
+    Err(Error::Custom(message))
+}
+
+#[scheme_fn("test-panic")]
+fn panics() {
+    panic!("test-panic called")
+}
+
+#[test]
+fn scheme_fns_are_defined() {
+    init();
+    let add = scheme_fns().find(|f| f.name == c"test-add").unwrap();
+    assert_eq!((add.required, add.optional), (1, 1));
+    for f in scheme_fns() {
+        unsafe { f.define() };
+    }
+    let eval = |expr: &CStr| unsafe { scm_c_eval_string(expr.as_ptr()) };
+    assert_eq!(try_scm_to_signed(eval(c"(test-add 2 3)")), Some(5));
+    assert_eq!(try_scm_to_signed(eval(c"(test-add 2)")), Some(3));
+
+    let caught = |key: &str, expr: &str| {
+        let expr = format!("(catch '{key} (lambda () {expr} #f) (lambda (key . args) key))");
+        let expr = std::ffi::CString::new(expr).unwrap();
+        try_scm_to_sym(eval(&expr)).as_deref() == Some(key)
+    };
+    assert!(caught("wrong-type-arg", "(test-add 'two)"));
+    assert!(caught("misc-error", "(test-fail \"failed\")"));
+    assert!(caught("rust-panic", "(test-panic)"));
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Counter(u64);
+
+static COUNTER: ForeignType<Counter> = ForeignType::new(c"counter");
+
+impl ForeignObject for Counter {
+    fn foreign_type() -> &'static ForeignType<Self> {
+        &COUNTER
+    }
+}
+
+#[scheme_fn("test-bump")]
+fn bump(counter: &mut Counter) -> u64 {
+    counter.0 += 1;
+    counter.0
+}
+
+#[test]
+fn foreign_objects() {
+    init();
+    let owned = COUNTER.make(Counter(1));
+    assert!(COUNTER.is_instance(owned));
+    assert!(!COUNTER.is_instance(1u64.serialize(Serializer::default()).unwrap()));
+    {
+        let _borrow = COUNTER.borrow_mut(owned).unwrap();
+        assert!(matches!(
+            COUNTER.borrow_mut(owned),
+            Err(Error::ForeignBorrowed)
+        ));
+    }
+    assert_eq!(COUNTER.get(owned).unwrap(), Counter(1));
+
+    // Scheme can use a lent value until the call returns.
+    unsafe {
+        scheme_fns()
+            .find(|f| f.name == c"test-bump")
+            .unwrap()
+            .define()
+    };
+    let bump = unsafe { scm_variable_ref(scm_c_lookup(c"test-bump".as_ptr())) };
+    let mut lent = Counter(5);
+    let expired = COUNTER.lend(&mut lent, |scm| {
+        assert_eq!(
+            try_scm_to_unsigned(unsafe { scm_call_1(bump, scm) }),
+            Some(6)
+        );
+        scm
+    });
+    assert_eq!(lent, Counter(6));
+    assert!(matches!(
+        COUNTER.borrow_mut(expired),
+        Err(Error::ForeignExpired)
+    ));
+
+    // `Foreign` copies values in and out of objects.
+    let scm = Foreign(Counter(7))
+        .serialize(Serializer::default())
+        .unwrap();
+    assert_eq!(COUNTER.get(scm).unwrap(), Counter(7));
+    let Foreign(copy) = Foreign::<Counter>::deserialize(Deserializer::new(scm)).unwrap();
+    assert_eq!(copy, Counter(7));
+    assert!(Foreign::<Counter>::deserialize(Deserializer::new(expired)).is_err());
+}
+
+/// A fresh pair, rooted only by the returned `Rooted`, and a weak vector
+/// holding it. The `Rooted` is boxed and this is out of line so that the
+/// pair isn't left on the caller's stack, where Guile would find it anyway.
+#[inline(never)]
+fn weakly_held_pair() -> (Box<Rooted>, SCM) {
+    unsafe {
+        let pair = scm_c_eval_string(c"(list 'kept)".as_ptr());
+        let weak = scm_call_1(guile_ref(c"weak-vector"), pair);
+        let rooted = Box::new(Rooted::new(pair));
+        // Protection is counted, so this leaves the original rooted.
+        drop(rooted.clone());
+        (rooted, weak)
+    }
+}
+
+/// Whether the pair held by `weak` is still alive after a full collection.
+#[inline(never)]
+fn survives_gc(weak: SCM) -> bool {
+    unsafe {
+        scm_gc();
+        scm_is_truthy(scm_call_2(
+            guile_ref(c"weak-vector-ref"),
+            weak,
+            scm_from_uint64(0),
+        ))
+    }
+}
+
+#[test]
+fn rooted_values_survive_gc() {
+    init();
+    let (rooted, weak) = weakly_held_pair();
+    assert!(survives_gc(weak));
+    drop(rooted);
+    assert!(!survives_gc(weak));
+}
+
+#[test]
+fn vectors_as_sequences() {
+    init();
+    let v = Vec::<u64>::deserialize(Deserializer::new(vector(&[1, 2, 3]))).unwrap();
+    assert_eq!(v, vec![1, 2, 3]);
+    let t = <(u64, u64)>::deserialize(Deserializer::new(vector(&[4, 5]))).unwrap();
+    assert_eq!(t, (4, 5));
+    let empty = Vec::<u64>::deserialize(Deserializer::new(vector(&[]))).unwrap();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn self_describing() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum Untagged {
+        Number(u64),
+        Text(String),
+        List(Vec<u64>),
+        Map(BTreeMap<String, u64>),
+    }
+    round_trip(Untagged::Number(3));
+    round_trip(Untagged::Text("three".to_string()));
+    round_trip(Untagged::List(vec![1, 2, 3]));
+    let hash_tables = SerializerConfig {
+        maps_as_hash_tables: true,
+        ..Default::default()
+    };
+    round_trip_with(
+        hash_tables,
+        Untagged::Map(BTreeMap::from([("a".to_string(), 1)])),
+    );
+    // Integer keys of hash tables come out as strings, as for JSON objects.
+    init();
+    let scm = BTreeMap::from([(1usize, 10u64), (2, 20)])
+        .serialize(Serializer::new(hash_tables))
+        .unwrap();
+    assert_eq!(
+        Untagged::deserialize(Deserializer::new(scm)).unwrap(),
+        Untagged::Map(BTreeMap::from([
+            ("1".to_string(), 10),
+            ("2".to_string(), 20)
+        ]))
+    );
+
+    #[derive(Serialize)]
+    struct Wide {
+        kept: u32,
+        dropped: Vec<String>,
+    }
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Narrow {
+        kept: u32,
+    }
+    init();
+    let scm = Wide {
+        kept: 1,
+        dropped: vec!["x".to_string()],
+    }
+    .serialize(Serializer::default())
+    .unwrap();
+    assert_eq!(
+        Narrow::deserialize(Deserializer::new(scm)).unwrap(),
+        Narrow { kept: 1 }
+    );
+    IgnoredAny::deserialize(Deserializer::new(scm)).unwrap();
+
+    // Records keep their type names, so variants can be told apart.
+    let ser = Serializer::new(SerializerConfig {
+        enums: EnumRepr::Record,
+        ..Default::default()
+    });
+    let scm = Enum::Struct { x: 1, y: 2 }.serialize(ser).unwrap();
+    let map = BTreeMap::<String, Untagged>::deserialize(Deserializer::new(scm)).unwrap();
+    assert_eq!(
+        map,
+        BTreeMap::from([
+            (
+                RECORD_TYPE_KEY.to_string(),
+                Untagged::Text("enum-struct".to_string())
+            ),
+            ("x".to_string(), Untagged::Number(1)),
+            ("y".to_string(), Untagged::Number(2)),
+        ])
+    );
+}
+
+#[test]
+fn errors_have_paths() {
+    init();
+
+    #[derive(Serialize)]
+    enum Other {
+        Bogus,
+    }
+    let scm = (Enum::Unit, Other::Bogus)
+        .serialize(Serializer::default())
+        .unwrap();
+    let err = <(Enum, Enum)>::deserialize(Deserializer::new(scm)).unwrap_err();
+    assert!(matches!(err.inner(), Error::Custom(_)), "{err:?}");
+    assert_eq!(
+        err.path().map(ToString::to_string).collect::<String>(),
+        "[1]"
+    );
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Mistyped {
+        parent: Option<String>,
+    }
+    let scm = Struct {
+        id: Newtype(3),
+        name: String::new(),
+        weights: vec![],
+        parent: Some(3),
+    }
+    .serialize(Serializer::default())
+    .unwrap();
+    let err = Mistyped::deserialize(Deserializer::new(scm)).unwrap_err();
+    assert!(
+        matches!(err.inner(), Error::ExpectedStringOrSymbol),
+        "{err:?}"
+    );
+    assert_eq!(err.to_string(), "at .parent: ExpectedStringOrSymbol");
+}